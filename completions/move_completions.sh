@@ -1,9 +1,11 @@
 #!/bin/bash
 
+# The completion scripts are generated by the release binary
 if [[ $TRAVIS_OS_NAME == "linux" ]]; then
-    for COMPLETION_FOLDER in completions/*; do
-        if [[ -d $COMPLETION_FOLDER ]]; then
-            cp $COMPLETION_FOLDER/* dist/
-        fi
-    done
-fi
\ No newline at end of file
+    GCLONE_BIN=target/release/gclone-bin
+    $GCLONE_BIN completions bash > dist/gclone.bash
+    $GCLONE_BIN completions zsh > dist/_gclone
+    $GCLONE_BIN completions fish > dist/gclone.fish
+    $GCLONE_BIN completions nushell > dist/gclone.nu
+    $GCLONE_BIN completions powershell > dist/gclone.ps1
+fi
@@ -0,0 +1,15 @@
+# gclone shell function for fish.
+# gclone-bin writes the directory to cd into to the file named by
+# $GCLONE_CD_FILE, so its output can go straight to the terminal.
+function gclone
+    set -l gclone_cd_file (mktemp)
+    or return
+    GCLONE_CD_FILE=$gclone_cd_file command gclone-bin $argv
+    set -l gclone_status $status
+    if test -s $gclone_cd_file
+        cd (cat $gclone_cd_file)
+        or set gclone_status $status
+    end
+    rm -f $gclone_cd_file
+    return $gclone_status
+end
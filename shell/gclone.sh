@@ -0,0 +1,14 @@
+# gclone shell function for bash and zsh.
+# gclone-bin writes the directory to cd into to the file named by
+# $GCLONE_CD_FILE, so its output can go straight to the terminal.
+gclone() {
+    local gclone_cd_file gclone_status
+    gclone_cd_file="$(mktemp)" || return
+    GCLONE_CD_FILE="$gclone_cd_file" command gclone-bin "$@"
+    gclone_status=$?
+    if [ -s "$gclone_cd_file" ]; then
+        cd -- "$(cat "$gclone_cd_file")" || gclone_status=$?
+    fi
+    rm -f "$gclone_cd_file"
+    return $gclone_status
+}
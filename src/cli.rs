@@ -0,0 +1,855 @@
+use crate::shell;
+use gclone::{Error, Result};
+
+/// A flag of a command. Flags with a value take it either from the next
+/// argument or after an `=` (`--workspace=work`, `-wwork`).
+#[derive(Clone, Copy)]
+pub struct Flag {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub value: FlagValue,
+    pub help: &'static str,
+    pub hidden: bool,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum FlagValue {
+    None,
+    Required(&'static str),
+    /// A value that defaults to the empty string when it's the last argument.
+    Optional(&'static str),
+}
+
+/// A positional argument of a command.
+pub struct Arg {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub required: bool,
+    pub values: Values,
+}
+
+/// What shell completion offers for an argument.
+pub enum Values {
+    None,
+    /// Repos, as listed by the `complete` command.
+    Repos,
+    /// The names of the commands.
+    Commands,
+    List(&'static [&'static str]),
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub flags: &'static [Flag],
+    pub args: &'static [Arg],
+    /// The arguments after `--`, forwarded as is, if the command takes any.
+    pub trailing: Option<Arg>,
+}
+
+const HELP: Flag = Flag {
+    short: Some('h'),
+    long: "help",
+    value: FlagValue::None,
+    help: "Print help",
+    hidden: false,
+};
+const VERSION: Flag = Flag {
+    short: Some('V'),
+    long: "version",
+    value: FlagValue::None,
+    help: "Print version",
+    hidden: false,
+};
+const NOCD: Flag = Flag {
+    short: Some('n'),
+    long: "nocd",
+    value: FlagValue::None,
+    help: "Don't cd into the repo",
+    hidden: false,
+};
+const LOCAL: Flag = Flag {
+    short: Some('l'),
+    long: "local",
+    value: FlagValue::None,
+    help: "Clone the repo in the current directory",
+    hidden: false,
+};
+const WORKSPACE: Flag = Flag {
+    short: Some('w'),
+    long: "workspace",
+    value: FlagValue::Required("NAME"),
+    help: "Use the workspace called NAME",
+    hidden: false,
+};
+const ADD_REMOTE: Flag = Flag {
+    short: Some('a'),
+    long: "add-remote",
+    value: FlagValue::Required("NAME"),
+    help: "Add the repo as remote NAME when its directory is a clone of another remote",
+    hidden: false,
+};
+const DOMAIN: Flag = Flag {
+    short: Some('d'),
+    long: "domain",
+    value: FlagValue::Required("DOMAIN"),
+    help: "Only include the repos of DOMAIN",
+    hidden: false,
+};
+const OWNER: Flag = Flag {
+    short: Some('o'),
+    long: "owner",
+    value: FlagValue::Required("OWNER"),
+    help: "Only include the repos of OWNER (and of its subgroups)",
+    hidden: false,
+};
+const FORMAT: Flag = Flag {
+    short: Some('f'),
+    long: "format",
+    value: FlagValue::Required("FORMAT"),
+    help: "Print repos as their shorthand (the default), path or url",
+    hidden: false,
+};
+const JSON: Flag = Flag {
+    short: None,
+    long: "json",
+    value: FlagValue::None,
+    help: "Print repos as a JSON array",
+    hidden: false,
+};
+const PULL: Flag = Flag {
+    short: Some('p'),
+    long: "pull",
+    value: FlagValue::None,
+    help: "Also fast-forward the current branch of each repo",
+    hidden: false,
+};
+const JOBS: Flag = Flag {
+    short: Some('j'),
+    long: "jobs",
+    value: FlagValue::Required("N"),
+    help: "Update N repos at once",
+    hidden: false,
+};
+const GET_BASE_DIR: Flag = Flag {
+    short: None,
+    long: "get-base-dir",
+    value: FlagValue::None,
+    help: "Print the base directory",
+    hidden: true,
+};
+const GET_BASE_DOMAIN: Flag = Flag {
+    short: None,
+    long: "get-base-domain",
+    value: FlagValue::None,
+    help: "Print the default domain",
+    hidden: true,
+};
+const MATCH_PREFIX: Flag = Flag {
+    short: None,
+    long: "match-prefix",
+    value: FlagValue::Optional("PREFIX"),
+    help: "Print the completions for PREFIX",
+    hidden: true,
+};
+
+const GIT_ARGS: Arg = Arg {
+    name: "GIT_ARGS",
+    help: "Extra arguments for git clone",
+    required: false,
+    values: Values::None,
+};
+
+const PATTERN: Arg = Arg {
+    name: "PATTERN",
+    help: "Only include the repos whose domain/path matches this glob",
+    required: false,
+    values: Values::None,
+};
+
+const REPO: Arg = Arg {
+    name: "REPO",
+    help: "The repo url, shorthand or local path",
+    required: true,
+    values: Values::Repos,
+};
+
+/// The invocation without a command, which clones. It also accepts the flags
+/// of the old, command-less interface.
+pub static MAIN: Command = Command {
+    name: "gclone",
+    about: "A simple git clone command line helper",
+    flags: &[
+        NOCD,
+        LOCAL,
+        WORKSPACE,
+        ADD_REMOTE,
+        HELP,
+        VERSION,
+        GET_BASE_DIR,
+        GET_BASE_DOMAIN,
+        MATCH_PREFIX,
+    ],
+    args: &[REPO],
+    trailing: Some(GIT_ARGS),
+};
+
+pub static CONFIG_KEYS: &[&str] = &["basePath", "defaultDomain", "localDomain"];
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "clone",
+        about: "Clone a repo into the tree and cd into it (the default)",
+        flags: &[NOCD, LOCAL, WORKSPACE, ADD_REMOTE, HELP],
+        args: &[REPO],
+        trailing: Some(GIT_ARGS),
+    },
+    Command {
+        name: "path",
+        about: "Print the directory a repo is (or would be) cloned to",
+        flags: &[WORKSPACE, HELP],
+        args: &[REPO],
+        trailing: None,
+    },
+    Command {
+        name: "which",
+        about: "Print the repo a directory belongs to, as a shorthand and ssh and https urls",
+        flags: &[HELP],
+        args: &[Arg {
+            name: "PATH",
+            help: "A directory inside the repo (defaults to the current directory)",
+            required: false,
+            values: Values::None,
+        }],
+        trailing: None,
+    },
+    Command {
+        name: "list",
+        about: "List the repos cloned in the tree",
+        flags: &[DOMAIN, OWNER, FORMAT, JSON, HELP],
+        args: &[PATTERN],
+        trailing: None,
+    },
+    Command {
+        name: "update",
+        about: "Fetch (or fast-forward) the repos cloned in the tree",
+        flags: &[DOMAIN, OWNER, PULL, JOBS, HELP],
+        args: &[PATTERN],
+        trailing: None,
+    },
+    Command {
+        name: "complete",
+        about: "Print the completions for a partial repo name",
+        flags: &[HELP],
+        args: &[Arg {
+            name: "PREFIX",
+            help: "The partial repo name",
+            required: false,
+            values: Values::Repos,
+        }],
+        trailing: None,
+    },
+    Command {
+        name: "config",
+        about: "Print the effective configuration",
+        flags: &[HELP],
+        args: &[Arg {
+            name: "KEY",
+            help: "Only print this setting (basePath, defaultDomain or localDomain)",
+            required: false,
+            values: Values::List(CONFIG_KEYS),
+        }],
+        trailing: None,
+    },
+    Command {
+        name: "init",
+        about: "Print the shell function and completions to source in your shell rc file",
+        flags: &[HELP],
+        args: &[Arg {
+            name: "SHELL",
+            help: "The shell to integrate with (bash, zsh or fish)",
+            required: true,
+            values: Values::List(shell::SHELLS),
+        }],
+        trailing: None,
+    },
+    Command {
+        name: "completions",
+        about: "Print the completion script for a shell",
+        flags: &[HELP],
+        args: &[Arg {
+            name: "SHELL",
+            help: "The shell to complete in (bash, zsh, fish, nushell or powershell)",
+            required: true,
+            values: Values::List(shell::COMPLETION_SHELLS),
+        }],
+        trailing: None,
+    },
+    Command {
+        name: "help",
+        about: "Print this message or the help of a command",
+        flags: &[],
+        args: &[Arg {
+            name: "COMMAND",
+            help: "The command to describe",
+            required: false,
+            values: Values::Commands,
+        }],
+        trailing: None,
+    },
+];
+
+pub struct CloneOptions {
+    pub nocd: bool,
+    pub local: bool,
+    pub workspace: Option<String>,
+    pub add_remote: Option<String>,
+    pub url: String,
+    pub git_args: Vec<String>,
+}
+
+/// Restricts commands working on the whole tree to some of its repos.
+pub struct RepoFilter {
+    pub domain: Option<String>,
+    pub owner: Option<String>,
+    pub pattern: Option<String>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ListFormat {
+    Shorthand,
+    Path,
+    Url,
+    Json,
+}
+
+pub struct UpdateOptions {
+    pub filter: RepoFilter,
+    pub pull: bool,
+    pub jobs: Option<usize>,
+}
+
+pub enum Action {
+    Clone(CloneOptions),
+    Path {
+        workspace: Option<String>,
+        url: String,
+    },
+    Which(Option<String>),
+    List(RepoFilter, ListFormat),
+    Update(UpdateOptions),
+    Complete(String),
+    Config(Option<String>),
+    Init(String),
+    Completions(String),
+    Help(&'static Command),
+    Version,
+}
+
+#[derive(Default)]
+struct Matches {
+    flags: Vec<(&'static str, String)>,
+    args: Vec<String>,
+    trailing: Vec<String>,
+}
+
+impl Matches {
+    fn has(&self, long: &str) -> bool {
+        self.flags.iter().any(|(name, _)| *name == long)
+    }
+
+    fn value(&self, long: &str) -> Option<String> {
+        self.flags
+            .iter()
+            .rfind(|(name, _)| *name == long)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn arg(&self, index: usize) -> Option<String> {
+        self.args.get(index).cloned()
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Action> {
+    let (command, rest) = match args.split_first() {
+        Some((first, rest)) => match find_command(first) {
+            Some(command) => (command, rest),
+            None => (&MAIN, args),
+        },
+        None => (&MAIN, args),
+    };
+    let matches = parse_command(command, rest)?;
+
+    if matches.has("help") {
+        return Ok(Action::Help(command));
+    }
+    if matches.has("version") {
+        return Ok(Action::Version);
+    }
+
+    match command.name {
+        "help" => match matches.arg(0) {
+            Some(name) => match find_command(&name) {
+                Some(command) => Ok(Action::Help(command)),
+                None => Err(Error::Usage(format!("Unknown command: {}", name))),
+            },
+            None => Ok(Action::Help(&MAIN)),
+        },
+        "path" => {
+            check_required_args(command, &matches)?;
+            Ok(Action::Path {
+                workspace: matches.value("workspace"),
+                url: matches.arg(0).unwrap_or_default(),
+            })
+        }
+        "which" => Ok(Action::Which(matches.arg(0))),
+        "list" => {
+            let format = match matches.value("format").as_deref() {
+                _ if matches.has("json") => ListFormat::Json,
+                None | Some("shorthand") => ListFormat::Shorthand,
+                Some("path") => ListFormat::Path,
+                Some("url") => ListFormat::Url,
+                Some(format) => {
+                    return Err(usage_error(command, format!("Unknown format: {}", format)))
+                }
+            };
+            Ok(Action::List(get_repo_filter(&matches), format))
+        }
+        "update" => {
+            let jobs = match matches.value("jobs") {
+                Some(jobs) => match jobs.parse::<usize>() {
+                    Ok(jobs) if jobs > 0 => Some(jobs),
+                    _ => {
+                        return Err(usage_error(
+                            command,
+                            format!("--jobs must be a positive integer: {}", jobs),
+                        ))
+                    }
+                },
+                None => None,
+            };
+            Ok(Action::Update(UpdateOptions {
+                filter: get_repo_filter(&matches),
+                pull: matches.has("pull"),
+                jobs,
+            }))
+        }
+        "complete" => Ok(Action::Complete(matches.arg(0).unwrap_or_default())),
+        "config" => Ok(Action::Config(matches.arg(0))),
+        "init" => {
+            check_required_args(command, &matches)?;
+            Ok(Action::Init(matches.arg(0).unwrap_or_default()))
+        }
+        "completions" => {
+            check_required_args(command, &matches)?;
+            Ok(Action::Completions(matches.arg(0).unwrap_or_default()))
+        }
+        _ => {
+            if matches.has("get-base-dir") {
+                return Ok(Action::Config(Some("basePath".to_string())));
+            }
+            if matches.has("get-base-domain") {
+                return Ok(Action::Config(Some("defaultDomain".to_string())));
+            }
+            if let Some(prefix) = matches.value("match-prefix") {
+                return Ok(Action::Complete(prefix));
+            }
+            check_required_args(command, &matches)?;
+            Ok(Action::Clone(CloneOptions {
+                nocd: matches.has("nocd"),
+                local: matches.has("local"),
+                workspace: matches.value("workspace"),
+                add_remote: matches.value("add-remote"),
+                url: matches.arg(0).unwrap_or_default(),
+                git_args: matches.trailing,
+            }))
+        }
+    }
+}
+
+fn get_repo_filter(matches: &Matches) -> RepoFilter {
+    RepoFilter {
+        domain: matches.value("domain"),
+        owner: matches.value("owner"),
+        pattern: matches.arg(0),
+    }
+}
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn parse_command(command: &Command, args: &[String]) -> Result<Matches> {
+    let mut matches = Matches::default();
+    let mut args_iter = args.iter();
+    let mut only_args = false;
+
+    while let Some(arg) = args_iter.next() {
+        if arg == "--" && !only_args && command.trailing.is_some() {
+            matches.trailing = args_iter.cloned().collect();
+            break;
+        } else if only_args || arg == "-" || !arg.starts_with('-') {
+            if matches.args.len() >= command.args.len() {
+                return Err(usage_error(
+                    command,
+                    format!("Unexpected argument: {}", arg),
+                ));
+            }
+            matches.args.push(arg.clone());
+        } else if arg == "--" {
+            only_args = true;
+        } else if let Some(long_arg) = arg.strip_prefix("--") {
+            let (name, inline_value) = match long_arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long_arg, None),
+            };
+            let flag = command
+                .flags
+                .iter()
+                .find(|flag| flag.long == name)
+                .ok_or_else(|| usage_error(command, format!("Unknown flag: --{}", name)))?;
+            let value = match (flag.value, inline_value) {
+                (FlagValue::None, Some(_)) => {
+                    return Err(usage_error(
+                        command,
+                        format!("--{} doesn't take a value", name),
+                    ))
+                }
+                (FlagValue::None, None) => String::new(),
+                (_, Some(value)) => value,
+                (_, None) => take_value(command, flag, &mut args_iter)?,
+            };
+            matches.flags.push((flag.long, value));
+        } else {
+            let shorts = &arg[1..];
+            for (index, short) in shorts.char_indices() {
+                let flag = command
+                    .flags
+                    .iter()
+                    .find(|flag| flag.short == Some(short))
+                    .ok_or_else(|| usage_error(command, format!("Unknown flag: -{}", short)))?;
+                if flag.value == FlagValue::None {
+                    matches.flags.push((flag.long, String::new()));
+                    continue;
+                }
+                let rest = &shorts[index + short.len_utf8()..];
+                let value = if rest.is_empty() {
+                    take_value(command, flag, &mut args_iter)?
+                } else {
+                    rest.to_string()
+                };
+                matches.flags.push((flag.long, value));
+                break;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+fn take_value<'a>(
+    command: &Command,
+    flag: &Flag,
+    args_iter: &mut impl Iterator<Item = &'a String>,
+) -> Result<String> {
+    match (flag.value, args_iter.next()) {
+        (_, Some(value)) => Ok(value.clone()),
+        (FlagValue::Optional(_), None) => Ok(String::new()),
+        (FlagValue::Required(value_name), None) => Err(usage_error(
+            command,
+            format!("--{} requires a {}", flag.long, value_name),
+        )),
+        (FlagValue::None, None) => Ok(String::new()),
+    }
+}
+
+fn check_required_args(command: &Command, matches: &Matches) -> Result<()> {
+    match command.args.get(matches.args.len()) {
+        Some(arg) if arg.required => Err(usage_error(
+            command,
+            format!("Missing argument: <{}>", arg.name),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn usage_error(command: &Command, message: String) -> Error {
+    let help_command = if command.name == MAIN.name {
+        "gclone --help".to_string()
+    } else {
+        format!("gclone {} --help", command.name)
+    };
+    Error::Usage(format!("{}\nRun `{}` for usage", message, help_command))
+}
+
+pub fn get_version() -> String {
+    format!("gclone {}", env!("CARGO_PKG_VERSION"))
+}
+
+pub fn get_help(command: &Command) -> String {
+    let is_main = command.name == MAIN.name;
+    let mut help = String::new();
+    if is_main {
+        help.push_str(&format!("{}\n", get_version()));
+    }
+    help.push_str(&format!("{}\n\n", command.about));
+
+    let usage_name = if is_main {
+        "gclone".to_string()
+    } else {
+        format!("gclone {}", command.name)
+    };
+    help.push_str(&format!(
+        "Usage: {}{}\n",
+        usage_name,
+        get_usage_args(command)
+    ));
+    if is_main {
+        help.push_str("       gclone <COMMAND> [ARGS]\n");
+    }
+
+    if is_main {
+        help.push_str("\nCommands:\n");
+        let rows: Vec<(String, &str)> = COMMANDS
+            .iter()
+            .map(|command| (command.name.to_string(), command.about))
+            .collect();
+        push_rows(&mut help, &rows);
+    }
+
+    if !command.args.is_empty() {
+        help.push_str("\nArguments:\n");
+        let rows: Vec<(String, &str)> = command
+            .args
+            .iter()
+            .chain(command.trailing.iter())
+            .map(|arg| (format!("<{}>", arg.name), arg.help))
+            .collect();
+        push_rows(&mut help, &rows);
+    }
+
+    let flags: Vec<&Flag> = command.flags.iter().filter(|flag| !flag.hidden).collect();
+    if !flags.is_empty() {
+        help.push_str("\nOptions:\n");
+        let rows: Vec<(String, &str)> = flags
+            .iter()
+            .map(|flag| (get_flag_usage(flag), flag.help))
+            .collect();
+        push_rows(&mut help, &rows);
+    }
+    help
+}
+
+fn get_usage_args(command: &Command) -> String {
+    let mut usage = String::new();
+    if command.flags.iter().any(|flag| !flag.hidden) {
+        usage.push_str(" [OPTIONS]");
+    }
+    for arg in command.args {
+        if arg.required {
+            usage.push_str(&format!(" <{}>", arg.name));
+        } else {
+            usage.push_str(&format!(" [{}]", arg.name));
+        }
+    }
+    if let Some(trailing) = &command.trailing {
+        usage.push_str(&format!(" [-- <{}>...]", trailing.name));
+    }
+    usage
+}
+
+fn get_flag_usage(flag: &Flag) -> String {
+    let mut usage = match flag.short {
+        Some(short) => format!("-{}, --{}", short, flag.long),
+        None => format!("    --{}", flag.long),
+    };
+    match flag.value {
+        FlagValue::None => {}
+        FlagValue::Required(name) => usage.push_str(&format!(" <{}>", name)),
+        FlagValue::Optional(name) => usage.push_str(&format!(" [{}]", name)),
+    }
+    usage
+}
+
+fn push_rows(help: &mut String, rows: &[(String, &str)]) {
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, description) in rows {
+        help.push_str(&format!(
+            "  {:width$}  {}\n",
+            name,
+            description,
+            width = width
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, Action, ListFormat, RepoFilter, UpdateOptions};
+
+    fn parse(args: &[&str]) -> gclone::Result<Action> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    fn parse_clone(args: &[&str]) -> super::CloneOptions {
+        match parse(args) {
+            Ok(Action::Clone(options)) => options,
+            _ => panic!("args should be parsed to a clone"),
+        }
+    }
+
+    #[test]
+    fn test_clone_without_command() {
+        let options = parse_clone(&["--nocd", "allonsy/gclone"]);
+        assert!(options.nocd);
+        assert!(!options.local);
+        assert_eq!(options.url, "allonsy/gclone");
+    }
+
+    #[test]
+    fn test_clone_command() {
+        let options = parse_clone(&["clone", "-nl", "-w", "work", "allonsy/gclone"]);
+        assert!(options.nocd);
+        assert!(options.local);
+        assert_eq!(options.workspace, Some("work".to_string()));
+        assert_eq!(options.url, "allonsy/gclone");
+
+        let options = parse_clone(&["clone", "--workspace=work", "allonsy/gclone"]);
+        assert_eq!(options.workspace, Some("work".to_string()));
+        let options = parse_clone(&["-wwork", "allonsy/gclone"]);
+        assert_eq!(options.workspace, Some("work".to_string()));
+        assert_eq!(options.add_remote, None);
+        let options = parse_clone(&["clone", "-a", "upstream", "allonsy/gclone"]);
+        assert_eq!(options.add_remote, Some("upstream".to_string()));
+    }
+
+    #[test]
+    fn test_legacy_helper_flags() {
+        assert!(matches!(
+            parse(&["--get-base-dir"]),
+            Ok(Action::Config(Some(key))) if key == "basePath"
+        ));
+        assert!(matches!(
+            parse(&["--get-base-domain"]),
+            Ok(Action::Config(Some(key))) if key == "defaultDomain"
+        ));
+        assert!(matches!(
+            parse(&["--match-prefix", "github.com:allonsy"]),
+            Ok(Action::Complete(prefix)) if prefix == "github.com:allonsy"
+        ));
+        assert!(matches!(
+            parse(&["--match-prefix"]),
+            Ok(Action::Complete(prefix)) if prefix.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_commands() {
+        assert!(matches!(
+            parse(&["path", "allonsy/gclone"]),
+            Ok(Action::Path { workspace: None, url }) if url == "allonsy/gclone"
+        ));
+        assert!(matches!(parse(&["complete"]), Ok(Action::Complete(prefix)) if prefix.is_empty()));
+        assert!(matches!(parse(&["config"]), Ok(Action::Config(None))));
+        assert!(matches!(parse(&["which"]), Ok(Action::Which(None))));
+        assert!(matches!(
+            parse(&["list"]),
+            Ok(Action::List(
+                RepoFilter {
+                    domain: None,
+                    owner: None,
+                    pattern: None
+                },
+                ListFormat::Shorthand
+            ))
+        ));
+        assert!(matches!(
+            parse(&["list", "-d", "gitlab.com", "--owner=group", "-f", "path", "*/repo"]),
+            Ok(Action::List(
+                RepoFilter { domain: Some(domain), owner: Some(owner), pattern: Some(pattern) },
+                ListFormat::Path,
+            )) if domain == "gitlab.com" && owner == "group" && pattern == "*/repo"
+        ));
+        assert!(matches!(
+            parse(&["list", "--format", "url", "--json"]),
+            Ok(Action::List(_, ListFormat::Json))
+        ));
+        assert!(parse(&["list", "--format", "yaml"]).is_err());
+        assert!(matches!(
+            parse(&["update", "-pj4", "--domain", "github.com"]),
+            Ok(Action::Update(UpdateOptions { filter, pull: true, jobs: Some(4) }))
+                if filter.domain.as_deref() == Some("github.com")
+        ));
+        assert!(matches!(
+            parse(&["update"]),
+            Ok(Action::Update(UpdateOptions {
+                pull: false,
+                jobs: None,
+                ..
+            }))
+        ));
+        assert!(parse(&["update", "-j", "0"]).is_err());
+        assert!(matches!(parse(&["which", "."]), Ok(Action::Which(Some(path))) if path == "."));
+        assert!(
+            matches!(parse(&["help", "path"]), Ok(Action::Help(command)) if command.name == "path")
+        );
+        assert!(
+            matches!(parse(&["path", "-h"]), Ok(Action::Help(command)) if command.name == "path")
+        );
+        assert!(matches!(parse(&["-V"]), Ok(Action::Version)));
+        assert!(matches!(parse(&["init", "zsh"]), Ok(Action::Init(shell)) if shell == "zsh"));
+        assert!(matches!(
+            parse(&["completions", "nushell"]),
+            Ok(Action::Completions(shell)) if shell == "nushell"
+        ));
+    }
+
+    #[test]
+    fn test_clone_command_name_as_repo() {
+        let options = parse_clone(&["clone", "list"]);
+        assert_eq!(options.url, "list");
+        let options = parse_clone(&["clone", "path"]);
+        assert_eq!(options.url, "path");
+    }
+
+    #[test]
+    fn test_double_dash_starts_git_args() {
+        // `--` starts the git arguments, so it doesn't escape the repo.
+        assert!(matches!(
+            parse(&["clone", "--", "path"]),
+            Err(gclone::Error::Usage(_))
+        ));
+
+        let options = parse_clone(&["clone", "allonsy/gclone", "--", "path"]);
+        assert_eq!(options.url, "allonsy/gclone");
+        assert_eq!(options.git_args, vec!["path"]);
+
+        let options = parse_clone(&["--nocd", "allonsy/gclone", "--", "--nocd"]);
+        assert!(options.nocd);
+        assert_eq!(options.git_args, vec!["--nocd"]);
+    }
+
+    #[test]
+    fn test_git_args() {
+        let options = parse_clone(&["allonsy/gclone", "--", "--depth", "1", "--", "-x"]);
+        assert_eq!(options.url, "allonsy/gclone");
+        assert_eq!(options.git_args, vec!["--depth", "1", "--", "-x"]);
+
+        let options = parse_clone(&["clone", "-n", "allonsy/gclone", "--"]);
+        assert!(options.git_args.is_empty());
+        assert!(parse(&["path", "allonsy/gclone", "--", "--depth", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["-"]).is_ok());
+        assert!(parse(&["-x", "allonsy/gclone"]).is_err());
+        assert!(parse(&["--unknown", "allonsy/gclone"]).is_err());
+        assert!(parse(&["--nocd=yes", "allonsy/gclone"]).is_err());
+        assert!(parse(&["--workspace"]).is_err());
+        assert!(parse(&["allonsy/gclone", "extra"]).is_err());
+        assert!(parse(&["help", "unknown"]).is_err());
+        assert!(parse(&["path"]).is_err());
+    }
+}
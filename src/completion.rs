@@ -1,157 +1,259 @@
 use crate::config;
+use crate::layout::Layout;
 use glob::glob;
 use std::cmp::Ordering;
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+/// Returns the completions for a partially typed repo, as `(hint, description)`
+/// pairs.
 pub fn get_matches(config: &config::Config, input: &str) -> Vec<(String, String)> {
     let mut matches = get_unsorted_matches(config, input);
     matches.sort_by(hint_sorter);
+    matches.dedup();
     matches
 }
 
 fn get_unsorted_matches(conf: &config::Config, input: &str) -> Vec<(String, String)> {
-    if input.contains(':') {
-        let broken_input: Vec<&str> = input.split(':').collect();
-        let domain = broken_input[0];
-        let path = broken_input[1];
-        return scan_single_tld(&conf, domain, path, true, true);
+    let domains = get_all_domains(conf);
+
+    if let Some((domain, path)) = input.split_once(':') {
+        return get_path_hints(conf, &domains, domain, path, true);
     }
 
     if input.contains('/') {
         let domain = conf.get_domain();
-        return scan_single_tld(&conf, domain, input, true, false);
+        return get_path_hints(conf, &domains, domain, input, false);
     }
 
-    if input == "" {
-        return get_top_level_hints(&conf);
+    if input.is_empty() {
+        return get_top_level_hints(conf, &domains);
     }
 
-    search_for_component(&conf, input)
+    search_for_component(conf, &domains, input)
+}
+
+/// Returns the directories under which the repos of `domain` are placed, one
+/// per workspace.
+fn get_domain_roots(conf: &config::Config, domain: &str) -> Vec<PathBuf> {
+    let root = conf.get_layout(domain).get_root(domain);
+    conf.get_all_base_paths()
+        .into_iter()
+        .map(|base_path| base_path.join(&root))
+        .collect()
 }
 
-fn get_all_non_default_tlds(conf: &config::Config) -> Vec<String> {
-    let default_tld = conf.get_domain();
-    let tlds = get_all_tlds(conf);
-    let mut new_tlds = Vec::new();
-    for tld in tlds {
-        if &tld != default_tld {
-            new_tlds.push(tld);
+fn get_all_domains(conf: &config::Config) -> Vec<String> {
+    let mut domains = Vec::new();
+
+    for domain in conf.get_configured_domains() {
+        if get_domain_roots(conf, domain)
+            .iter()
+            .any(|root| root.is_dir())
+        {
+            domains.push(domain.to_string());
         }
     }
 
-    new_tlds
+    // Domains with a layout of their own may place their repos right next to
+    // the roots of other domains, in which case only directories that look
+    // like host names are taken as domains.
+    let shared_roots: Vec<PathBuf> = conf
+        .get_layout_domains()
+        .into_iter()
+        .flat_map(|domain| get_domain_roots(conf, domain))
+        .collect();
+
+    for base_path in conf.get_all_base_paths() {
+        for domain in discover_domains(conf, base_path, &shared_roots) {
+            domains.push(domain);
+        }
+    }
+
+    domains.sort();
+    domains.dedup();
+    domains
 }
 
-fn get_all_tlds(conf: &config::Config) -> Vec<String> {
-    let base_path = conf.get_base_path();
-    let mut tlds = Vec::new();
-
-    let cur_dir_res = fs::read_dir(base_path);
-    if cur_dir_res.is_err() {
-        return tlds;
-    }
-    let base_dir = cur_dir_res.unwrap();
-    for dir in base_dir {
-        if dir.is_ok() {
-            let dir_path = dir.unwrap();
-            if dir_path.path().is_dir() {
-                tlds.push(
-                    dir_path
-                        .path()
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string(),
-                );
-            }
+fn discover_domains(
+    conf: &config::Config,
+    base_path: &Path,
+    shared_roots: &[PathBuf],
+) -> Vec<String> {
+    let mut domains = Vec::new();
+    let default_layout = conf.get_default_layout();
+    let root_glob = match default_layout.get_root_glob() {
+        Some(root_glob) => root_glob,
+        None => return domains,
+    };
+    let pattern = format!(
+        "{}/{}",
+        glob::Pattern::escape(base_path.to_str().unwrap()),
+        root_glob
+    );
+    let glob_results = match glob(&pattern) {
+        Ok(glob_results) => glob_results,
+        Err(_) => {
+            eprintln!("invalid glob pattern");
+            return domains;
         }
+    };
+
+    for root_path in glob_results.filter_map(Result::ok) {
+        if !root_path.is_dir() {
+            continue;
+        }
+        let rel_root = match root_path.strip_prefix(base_path) {
+            Ok(rel_root) => rel_root,
+            Err(_) => continue,
+        };
+        let domain = match rel_root
+            .to_str()
+            .and_then(|rel_root| default_layout.get_root_domain(&rel_root.replace('\\', "/")))
+        {
+            Some(domain) => domain,
+            None => continue,
+        };
+
+        let is_shared = shared_roots
+            .iter()
+            .any(|shared_root| root_path.starts_with(shared_root) && root_path != *shared_root);
+        if is_shared && !domain.contains('.') {
+            continue;
+        }
+        domains.push(domain);
     }
 
-    tlds
+    domains
 }
 
-fn scan_single_tld(
+/// Collects the repo paths of `domain` (and all their parent paths) having at
+/// most `max_components` components. Only directories that can lead to
+/// matches for `input` are descended into.
+fn get_sub_paths(
     conf: &config::Config,
-    tld: &str,
+    domains: &[String],
+    domain: &str,
+    input: &str,
+    max_components: usize,
+) -> BTreeSet<String> {
+    let other_roots: Vec<PathBuf> = domains
+        .iter()
+        .filter(|other_domain| *other_domain != domain)
+        .flat_map(|other_domain| get_domain_roots(conf, other_domain))
+        .collect();
+    let walk = SubPathWalk {
+        layout: conf.get_layout(domain),
+        domain,
+        input,
+        max_components,
+        other_roots: &other_roots,
+    };
+
+    let mut sub_paths = BTreeSet::new();
+    for root in get_domain_roots(conf, domain) {
+        walk_sub_paths(&walk, &root, "", &mut sub_paths);
+    }
+    sub_paths
+}
+
+struct SubPathWalk<'a> {
+    layout: &'a Layout,
+    domain: &'a str,
+    input: &'a str,
+    max_components: usize,
+    other_roots: &'a [PathBuf],
+}
+
+fn walk_sub_paths(
+    walk: &SubPathWalk,
+    dir: &Path,
+    rel_path: &str,
+    sub_paths: &mut BTreeSet<String>,
+) {
+    let search_dir = match fs::read_dir(dir) {
+        Ok(search_dir) => search_dir,
+        Err(_) => return,
+    };
+
+    for dir_entry in search_dir.flatten() {
+        let entry_path = dir_entry.path();
+        if !entry_path.is_dir() || walk.other_roots.contains(&entry_path) {
+            continue;
+        }
+        // Hidden directories hold no repos, only things like running clones.
+        let file_name = match dir_entry.file_name().to_str() {
+            Some(file_name) if !file_name.starts_with('.') => file_name.to_string(),
+            _ => continue,
+        };
+        let entry_rel_path = if rel_path.is_empty() {
+            file_name
+        } else {
+            format!("{}/{}", rel_path, file_name)
+        };
+
+        let sub_path = walk.layout.get_sub_path(walk.domain, &entry_rel_path);
+        let components: Vec<&str> = sub_path.split('/').collect();
+        for count in 1..=components.len().min(walk.max_components) {
+            sub_paths.insert(components[..count].join("/"));
+        }
+
+        let is_relevant =
+            sub_path.starts_with(walk.input) || walk.input.starts_with(&format!("{}/", sub_path));
+        if components.len() < walk.max_components && is_relevant {
+            walk_sub_paths(walk, &entry_path, &entry_rel_path, sub_paths);
+        }
+    }
+}
+
+fn get_path_hints(
+    conf: &config::Config,
+    domains: &[String],
+    domain: &str,
     path: &str,
-    add_short_desc: bool,
-    add_tld_prefix: bool,
+    add_domain_prefix: bool,
 ) -> Vec<(String, String)> {
     let mut completions = Vec::new();
-    let base_path = conf.get_base_path();
-    let full_path = format!("{}/{}/{}*", base_path.to_str().unwrap(), tld, path);
-    let path_prefix = PathBuf::from(format!("{}/{}", base_path.to_str().unwrap(), tld));
-    let glob_results = glob(&full_path);
-    if glob_results.is_err() {
-        eprintln!("invalid glob pattern");
-        return completions;
-    }
-    for entry in glob_results.unwrap().filter_map(Result::ok) {
-        let glob_path = entry;
-        if glob_path.is_dir() {
-            let relative_path = glob_path.strip_prefix(path_prefix.clone());
-            if relative_path.is_ok() {
-                let rel_path = relative_path.unwrap();
-                let input_name = if add_tld_prefix {
-                    format!("{}:{}/", tld, rel_path.display())
-                } else {
-                    format!("{}/", rel_path.display())
-                };
-                let short_name = if add_short_desc {
-                    format!("{}/", glob_path.file_name().unwrap().to_str().unwrap())
-                } else {
-                    input_name.clone()
-                };
-                completions.push((input_name, short_name));
-            }
+    let level = path.matches('/').count() + 1;
+
+    for sub_path in get_sub_paths(conf, domains, domain, path, level) {
+        if !sub_path.starts_with(path) || sub_path.split('/').count() != level {
+            continue;
         }
+        let input_name = if add_domain_prefix {
+            format!("{}:{}/", domain, sub_path)
+        } else {
+            format!("{}/", sub_path)
+        };
+        let short_name = format!("{}/", sub_path.rsplit('/').next().unwrap());
+        completions.push((input_name, short_name));
     }
 
     completions
 }
 
-fn get_top_level_hints(conf: &config::Config) -> Vec<(String, String)> {
+fn get_top_level_hints(conf: &config::Config, domains: &[String]) -> Vec<(String, String)> {
     let mut hints = Vec::new();
 
-    let non_default_tlds = get_all_tlds(conf);
-    for domain in non_default_tlds {
+    for domain in domains {
         let domain_colon = format!("{}:", domain);
         hints.push((domain_colon.clone(), domain_colon));
     }
 
     let default_domain = conf.get_domain();
-    let base_path = conf.get_base_path();
-    let search_path = base_path.join(default_domain);
-
-    let search_dir_res = fs::read_dir(search_path);
-    if search_dir_res.is_err() {
-        return hints;
-    }
-
-    let search_dir = search_dir_res.unwrap();
-    for dir in search_dir {
-        if dir.is_ok() {
-            let dir_path = dir.unwrap();
-            if dir_path.path().is_dir() {
-                let file_name = format!("{}/", dir_path.file_name().to_str().unwrap());
-                hints.push((file_name.clone(), file_name));
-            }
-        }
-    }
+    hints.extend(get_path_hints(conf, domains, default_domain, "", false));
 
     hints
 }
 
-fn search_for_component(conf: &config::Config, input: &str) -> Vec<(String, String)> {
+fn search_for_component(
+    conf: &config::Config,
+    domains: &[String],
+    input: &str,
+) -> Vec<(String, String)> {
     let mut hints = Vec::new();
     let default_domain = conf.get_domain();
-    let max_depth = conf.get_default_search_depth();
-
-    let base_path = conf.get_base_path();
-    let default_path = format!("{}", base_path.join(default_domain).display());
-    let default_hints = list_components(&default_path, "", input, 0, max_depth);
 
     if default_domain.starts_with(input) {
         hints.push((
@@ -160,63 +262,26 @@ fn search_for_component(conf: &config::Config, input: &str) -> Vec<(String, Stri
         ));
     }
 
-    for (hint, short) in default_hints {
-        hints.push((hint.clone(), short.clone()));
+    let max_depth = conf.get_search_depth(default_domain);
+    for sub_path in get_sub_paths(conf, domains, default_domain, "", max_depth) {
+        if sub_path.rsplit('/').next().unwrap().starts_with(input) {
+            let hint_name = format!("{}/", sub_path);
+            hints.push((hint_name.clone(), hint_name));
+        }
     }
 
-    for tld in get_all_non_default_tlds(conf) {
-        if tld.starts_with(input) {
-            hints.push((format!("{}:", tld), format!("{}:", tld)));
+    for domain in domains {
+        if domain == default_domain {
+            continue;
         }
-        let search_path = format!("{}/{}", base_path.display(), tld);
-        for hint in list_components(&search_path, &format!("{}:", tld), input, 0, max_depth) {
-            hints.push(hint);
+        if domain.starts_with(input) {
+            hints.push((format!("{}:", domain), format!("{}:", domain)));
         }
-    }
-
-    hints
-}
-
-fn list_components(
-    path: &str,
-    prefix: &str,
-    input: &str,
-    depth: usize,
-    max_depth: usize,
-) -> Vec<(String, String)> {
-    if depth >= max_depth {
-        return Vec::new();
-    }
-
-    let mut hints = Vec::new();
-
-    let search_dir_res = fs::read_dir(path);
-    if search_dir_res.is_err() {
-        return hints;
-    }
-
-    let search_dir = search_dir_res.unwrap();
-    for dir in search_dir {
-        if dir.is_ok() {
-            let dir_path = dir.unwrap();
-            if dir_path.path().is_dir() {
-                let file_path = dir_path.path();
-                let file_name = file_path.file_name().unwrap().to_str().unwrap();
-                if file_name.starts_with(input) {
-                    let hint_name = format!("{}{}/", prefix, file_name);
-                    hints.push((hint_name.clone(), hint_name.clone()));
-                }
-                let sub_path = format!("{}/{}", path, file_name);
-                let sub_hints = list_components(
-                    &sub_path,
-                    &format!("{}{}/", prefix, file_name),
-                    input,
-                    depth + 1,
-                    max_depth,
-                );
-                for sub_hint in sub_hints {
-                    hints.push(sub_hint);
-                }
+        let max_depth = conf.get_search_depth(domain);
+        for sub_path in get_sub_paths(conf, domains, domain, "", max_depth) {
+            if sub_path.rsplit('/').next().unwrap().starts_with(input) {
+                let hint_name = format!("{}:{}/", domain, sub_path);
+                hints.push((hint_name.clone(), hint_name));
             }
         }
     }
@@ -224,7 +289,7 @@ fn list_components(
     hints
 }
 
-pub fn hint_sorter(tup1: &(String, String), tup2: &(String, String)) -> Ordering {
+fn hint_sorter(tup1: &(String, String), tup2: &(String, String)) -> Ordering {
     let desc1 = &tup1.1;
     let desc2 = &tup2.1;
 
@@ -242,9 +307,10 @@ pub fn hint_sorter(tup1: &(String, String), tup2: &(String, String)) -> Ordering
 mod test {
     use super::get_matches;
     use crate::config;
+    use crate::layout::Layout;
 
     fn get_testing_config() -> config::Config {
-        let mut conf = config::get_config();
+        let mut conf = config::Config::default();
         let mut base_path = std::env::current_dir().unwrap();
         base_path.push("test");
         base_path.push("completions");
@@ -253,6 +319,35 @@ mod test {
         conf
     }
 
+    fn get_layout_testing_config() -> config::Config {
+        let mut conf = get_testing_config();
+        let mut base_path = std::env::current_dir().unwrap();
+        base_path.push("test");
+        base_path.push("layouts");
+        conf.set_base_path(base_path);
+        conf.set_layout(Layout::parse("{domain}/{owner}--{repo}").unwrap());
+        conf.set_domain_layout("github.com", Layout::parse("{owner}/{repo}").unwrap());
+        conf
+    }
+
+    fn get_workspace_testing_config() -> config::Config {
+        let mut conf = get_testing_config();
+        let mut base_path = std::env::current_dir().unwrap();
+        base_path.push("test");
+        base_path.push("workspaces");
+        conf.add_workspace("work", base_path, &["gitlab.corp.example"]);
+        conf
+    }
+
+    fn get_local_testing_config() -> config::Config {
+        let mut conf = get_testing_config();
+        let mut base_path = std::env::current_dir().unwrap();
+        base_path.push("test");
+        base_path.push("local");
+        conf.set_base_path(base_path);
+        conf
+    }
+
     fn conv_matches(expected_mathes: Vec<(&str, &str)>) -> Vec<(String, String)> {
         let mut new_matches = Vec::new();
         for (a, b) in expected_mathes {
@@ -275,6 +370,14 @@ mod test {
         assert_eq!(matches, conv_matches(expected_matches));
     }
 
+    #[test]
+    fn test_hidden_dirs_are_skipped() {
+        let conf = get_testing_config();
+        let matches = get_matches(&conf, "allonsy/");
+        let expected_matches = vec![("allonsy/gclone/", "gclone/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
     #[test]
     fn test_short_path() {
         let conf = get_testing_config();
@@ -321,6 +424,20 @@ mod test {
         assert_eq!(matches, conv_matches(expected_matches));
     }
 
+    #[test]
+    fn test_domain_search_depth() {
+        let mut conf = get_testing_config();
+        assert_eq!(get_matches(&conf, "cod"), Vec::new());
+
+        conf.set_search_depth("aur.archlinux.org", 3);
+        let matches = get_matches(&conf, "cod");
+        let expected_matches = vec![(
+            "aur.archlinux.org:gclone/src/code/",
+            "aur.archlinux.org:gclone/src/code/",
+        )];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
     #[test]
     fn test_user_search() {
         let conf = get_testing_config();
@@ -331,4 +448,98 @@ mod test {
         ];
         assert_eq!(matches, conv_matches(expected_matches));
     }
+
+    #[test]
+    fn test_local_path() {
+        let conf = get_local_testing_config();
+        let matches = get_matches(&conf, "local:srv/mirrors/");
+        let expected_matches = vec![("local:srv/mirrors/acme/", "acme/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_no_input() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "");
+        let expected_matches = vec![
+            ("allonsy/", "allonsy/"),
+            ("github.com:", "github.com:"),
+            ("gitlab.com:", "gitlab.com:"),
+        ];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_short_path() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "allonsy/gclone/");
+        let expected_matches = vec![("allonsy/gclone/src/", "src/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_flat_owner() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "gitlab.com:gr");
+        let expected_matches = vec![("gitlab.com:group/", "group/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_flat_repo() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "gitlab.com:group/");
+        let expected_matches = vec![("gitlab.com:group/tool/", "tool/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_flat_repo_subdir() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "gitlab.com:group/tool/d");
+        let expected_matches = vec![("gitlab.com:group/tool/docs/", "docs/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_layout_repo_name() {
+        let conf = get_layout_testing_config();
+        let matches = get_matches(&conf, "to");
+        let expected_matches = vec![("gitlab.com:group/tool/", "gitlab.com:group/tool/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_workspace_no_input() {
+        let conf = get_workspace_testing_config();
+        let matches = get_matches(&conf, "");
+        let expected_matches = vec![
+            ("acme/", "acme/"),
+            ("allonsy/", "allonsy/"),
+            ("aur.archlinux.org:", "aur.archlinux.org:"),
+            ("github.com:", "github.com:"),
+            ("gitlab.com:", "gitlab.com:"),
+            ("gitlab.corp.example:", "gitlab.corp.example:"),
+        ];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_workspace_short_path() {
+        let conf = get_workspace_testing_config();
+        let matches = get_matches(&conf, "gitlab.corp.example:team/");
+        let expected_matches = vec![("gitlab.corp.example:team/service/", "service/")];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
+
+    #[test]
+    fn test_workspace_repo_name() {
+        let conf = get_workspace_testing_config();
+        let matches = get_matches(&conf, "ser");
+        let expected_matches = vec![(
+            "gitlab.corp.example:team/service/",
+            "gitlab.corp.example:team/service/",
+        )];
+        assert_eq!(matches, conv_matches(expected_matches));
+    }
 }
@@ -1,25 +1,87 @@
-use dirs;
+use crate::error::{Error, Result};
+use crate::layout::Layout;
+use glob::Pattern;
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
-use std::str::FromStr;
 use toml::Value;
 
 static GCLONE_CONF_FILE_VAR_NAME: &str = "GCLONE_CONF_FILE";
+static DEFAULT_WORKSPACE_NAME: &str = "default";
 
+#[derive(Default)]
+struct DomainConfig {
+    https: Option<bool>,
+    ssh_user: Option<String>,
+    search_depth: Option<usize>,
+    ssh_port: Option<u16>,
+    https_port: Option<u16>,
+    layout: Option<Layout>,
+    clone_args: Vec<String>,
+}
+
+/// An additional tree root that repos are routed to by domain and/or owner.
+struct Workspace {
+    name: String,
+    base_path: PathBuf,
+    domains: Vec<Pattern>,
+    owners: Vec<Pattern>,
+}
+
+impl Workspace {
+    fn matches(&self, domain: &str, sub_path: &str) -> bool {
+        if self.domains.is_empty() && self.owners.is_empty() {
+            return false;
+        }
+        let domain_matches =
+            self.domains.is_empty() || self.domains.iter().any(|pattern| pattern.matches(domain));
+
+        let components: Vec<&str> = sub_path.split('/').filter(|c| !c.is_empty()).collect();
+        let owner_components = &components[..components.len().saturating_sub(1)];
+        let owner_matches = self.owners.is_empty()
+            || (1..=owner_components.len()).any(|count| {
+                let owner = owner_components[..count].join("/");
+                self.owners.iter().any(|pattern| pattern.matches(&owner))
+            });
+
+        domain_matches && owner_matches
+    }
+}
+
+/// The user settings, read from `$GCLONE_CONF_FILE` or the `gclone/gclone.toml`
+/// file in the config directory.
 pub struct Config {
     base_path: PathBuf,
     default_https: bool,
     default_domain: String,
     default_search_depth: usize,
+    default_clone_args: Vec<String>,
+    verify_remote: bool,
+    protocol_fallback: bool,
+    update_jobs: usize,
+    layout: Layout,
+    local_domain: String,
+    local_paths: Vec<(PathBuf, String)>,
+    domains: HashMap<String, DomainConfig>,
+    workspaces: Vec<Workspace>,
 }
 
 impl Config {
     fn new() -> Config {
         Config {
-            base_path: get_default_base_path(),
+            base_path: get_default_base_path().unwrap_or_default(),
             default_domain: get_default_domain().to_string(),
             default_https: get_default_is_https(),
             default_search_depth: get_default_search_depth(),
+            default_clone_args: Vec::new(),
+            verify_remote: false,
+            protocol_fallback: false,
+            update_jobs: get_default_update_jobs(),
+            layout: Layout::default(),
+            local_domain: get_default_local_domain().to_string(),
+            local_paths: Vec::new(),
+            domains: HashMap::new(),
+            workspaces: Vec::new(),
         }
     }
 
@@ -27,16 +89,142 @@ impl Config {
         &self.base_path
     }
 
+    /// Returns the base path of the first workspace whose rules match the repo,
+    /// or `basePath` if none do.
+    pub fn get_base_path_for(&self, domain: &str, sub_path: &str) -> &PathBuf {
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.matches(domain, sub_path))
+            .map_or(&self.base_path, |workspace| &workspace.base_path)
+    }
+
+    pub fn get_workspace_base_path(&self, name: &str) -> Option<&PathBuf> {
+        if name == DEFAULT_WORKSPACE_NAME {
+            return Some(&self.base_path);
+        }
+        self.workspaces
+            .iter()
+            .find(|workspace| workspace.name == name)
+            .map(|workspace| &workspace.base_path)
+    }
+
+    /// Returns the base paths of all workspaces, starting with `basePath`.
+    pub fn get_all_base_paths(&self) -> Vec<&PathBuf> {
+        let mut base_paths = vec![&self.base_path];
+        for workspace in &self.workspaces {
+            if !base_paths.contains(&&workspace.base_path) {
+                base_paths.push(&workspace.base_path);
+            }
+        }
+        base_paths
+    }
+
     pub fn get_domain(&self) -> &String {
         &self.default_domain
     }
 
-    pub fn get_is_https(&self) -> bool {
-        self.default_https
+    pub fn get_layout(&self, domain: &str) -> &Layout {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.layout.as_ref())
+            .unwrap_or(&self.layout)
+    }
+
+    pub fn get_default_layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Returns the domains that have a layout of their own.
+    pub fn get_layout_domains(&self) -> Vec<&String> {
+        self.domains
+            .iter()
+            .filter(|(_, domain_conf)| domain_conf.layout.is_some())
+            .map(|(domain, _)| domain)
+            .collect()
+    }
+
+    /// Returns every domain mentioned in the config.
+    pub fn get_configured_domains(&self) -> Vec<&String> {
+        let mut domains: Vec<&String> = self.domains.keys().collect();
+        domains.push(&self.default_domain);
+        domains.push(&self.local_domain);
+        for (_, local_domain) in &self.local_paths {
+            domains.push(local_domain);
+        }
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+
+    pub fn get_local_domain(&self) -> &String {
+        &self.local_domain
+    }
+
+    pub fn get_local_paths(&self) -> &Vec<(PathBuf, String)> {
+        &self.local_paths
+    }
+
+    pub fn get_domain_is_https(&self, domain: &str) -> Option<bool> {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.https)
+    }
+
+    pub fn get_is_https_for(&self, domain: &str) -> bool {
+        self.get_domain_is_https(domain)
+            .unwrap_or(self.default_https)
+    }
+
+    pub fn get_ssh_user(&self, domain: &str) -> Option<&String> {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.ssh_user.as_ref())
+    }
+
+    pub fn get_search_depth(&self, domain: &str) -> usize {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.search_depth)
+            .unwrap_or(self.default_search_depth)
+    }
+
+    pub fn get_ssh_port(&self, domain: &str) -> Option<u16> {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.ssh_port)
+    }
+
+    pub fn get_https_port(&self, domain: &str) -> Option<u16> {
+        self.domains
+            .get(domain)
+            .and_then(|domain_conf| domain_conf.https_port)
+    }
+
+    /// Returns the extra `git clone` arguments for repos of `domain`: the
+    /// global ones followed by the domain specific ones.
+    pub fn get_clone_args(&self, domain: &str) -> Vec<String> {
+        let mut clone_args = self.default_clone_args.clone();
+        if let Some(domain_conf) = self.domains.get(domain) {
+            clone_args.extend(domain_conf.clone_args.iter().cloned());
+        }
+        clone_args
+    }
+
+    /// Whether to check with `git ls-remote` that the repo is reachable
+    /// before creating any directory for it.
+    pub fn get_verify_remote(&self) -> bool {
+        self.verify_remote
+    }
+
+    /// Whether to retry a clone over https when ssh authentication fails, and
+    /// over ssh when https authentication fails.
+    pub fn get_protocol_fallback(&self) -> bool {
+        self.protocol_fallback
     }
 
-    pub fn get_default_search_depth(&self) -> usize {
-        self.default_search_depth
+    /// How many repos the `update` command updates at once.
+    pub fn get_update_jobs(&self) -> usize {
+        self.update_jobs
     }
 
     #[cfg(test)]
@@ -44,96 +232,367 @@ impl Config {
         self.base_path = new_base_path;
     }
 
+    #[cfg(test)]
+    pub fn add_workspace(&mut self, name: &str, base_path: PathBuf, domains: &[&str]) {
+        self.workspaces.push(Workspace {
+            name: name.to_string(),
+            base_path,
+            domains: domains.iter().map(|d| Pattern::new(d).unwrap()).collect(),
+            owners: Vec::new(),
+        });
+    }
+
     #[cfg(test)]
     pub fn set_default_domain(&mut self, new_domain: String) {
         self.default_domain = new_domain;
     }
+
+    #[cfg(test)]
+    pub fn set_ssh_user(&mut self, domain: &str, ssh_user: String) {
+        self.domains.entry(domain.to_string()).or_default().ssh_user = Some(ssh_user);
+    }
+
+    #[cfg(test)]
+    pub fn set_domain_is_https(&mut self, domain: &str, https: bool) {
+        self.domains.entry(domain.to_string()).or_default().https = Some(https);
+    }
+
+    #[cfg(test)]
+    pub fn set_search_depth(&mut self, domain: &str, search_depth: usize) {
+        self.domains
+            .entry(domain.to_string())
+            .or_default()
+            .search_depth = Some(search_depth);
+    }
+
+    #[cfg(test)]
+    pub fn set_ssh_port(&mut self, domain: &str, port: u16) {
+        self.domains.entry(domain.to_string()).or_default().ssh_port = Some(port);
+    }
+
+    #[cfg(test)]
+    pub fn set_https_port(&mut self, domain: &str, port: u16) {
+        self.domains
+            .entry(domain.to_string())
+            .or_default()
+            .https_port = Some(port);
+    }
+
+    #[cfg(test)]
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    #[cfg(test)]
+    pub fn set_domain_layout(&mut self, domain: &str, layout: Layout) {
+        self.domains.entry(domain.to_string()).or_default().layout = Some(layout);
+    }
+
+    #[cfg(test)]
+    pub fn add_local_path(&mut self, local_path: PathBuf, domain: String) {
+        self.local_paths.push((local_path, domain));
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
 }
 
-pub fn get_config() -> Config {
+/// Reads the config file on top of the default settings.
+pub fn get_config() -> Result<Config> {
     let mut conf = Config::new();
+    read_config_file(&mut conf)?;
+
+    if conf.base_path.as_os_str().is_empty() {
+        return Err(Error::Config(
+            "Unable to determine home directory, please set basePath".to_string(),
+        ));
+    }
+
+    Ok(conf)
+}
+
+fn read_config_file(conf: &mut Config) -> Result<()> {
     let config_file_env_var = env::var(GCLONE_CONF_FILE_VAR_NAME);
 
-    let config_file_path =
-        if config_file_env_var.is_ok() && config_file_env_var.as_ref().unwrap() != "" {
-            let fpath_str = config_file_env_var.unwrap();
-            PathBuf::from(fpath_str)
-        } else {
-            let conf_dir = dirs::config_dir();
-            if conf_dir.is_none() {
-                return conf;
-            }
+    let config_file_path = match config_file_env_var {
+        Ok(fpath_str) if !fpath_str.is_empty() => PathBuf::from(fpath_str),
+        _ => {
+            let conf_dir = match dirs::config_dir() {
+                Some(conf_dir) => conf_dir,
+                None => return Ok(()),
+            };
 
-            let mut config_file_path = conf_dir.unwrap();
+            let mut config_file_path = conf_dir;
             config_file_path.push("gclone");
             config_file_path.push("gclone.toml");
             config_file_path
-        };
+        }
+    };
     if !config_file_path.exists() {
-        return conf;
+        return Ok(());
     }
 
-    let config_file_contents = std::fs::read_to_string(config_file_path);
-    if config_file_contents.is_err() {
-        eprintln!("gclone config error: Unable to read config file");
-        return conf;
-    }
+    let config_file_contents = std::fs::read_to_string(&config_file_path).map_err(|err| {
+        Error::Config(format!(
+            "Unable to read config file {}: {}",
+            config_file_path.display(),
+            err
+        ))
+    })?;
 
-    let parsed_val = config_file_contents.unwrap().parse::<Value>();
-    if parsed_val.is_err() {
-        eprintln!("gclone config error: Unable to parse config file");
-        return conf;
-    }
+    let parsed_toml = config_file_contents
+        .parse::<Value>()
+        .map_err(|err| Error::Config(format!("Unable to parse config file: {}", err)))?;
 
-    let parsed_toml = parsed_val.unwrap();
     if parsed_toml.is_table() {
         for (key, val) in parsed_toml.as_table().unwrap() {
-            parse_value(&mut conf, key, val);
+            parse_value(conf, key, val)?;
         }
     }
-
-    conf
+    Ok(())
 }
 
-fn parse_value(conf: &mut Config, key_name: &str, val: &Value) {
-    if val.is_str() {
+fn parse_value(conf: &mut Config, key_name: &str, val: &Value) -> Result<()> {
+    if key_name == "defaultCloneArgs" {
+        conf.default_clone_args = parse_string_list(val)
+            .map_err(|err| Error::Config(format!("defaultCloneArgs: {}", err)))?;
+    } else if key_name == "updateJobs" {
+        conf.update_jobs = match val.as_integer() {
+            Some(jobs) if jobs > 0 => jobs as usize,
+            _ => {
+                return Err(Error::Config(
+                    "updateJobs must be a positive integer".to_string(),
+                ))
+            }
+        };
+    } else if val.is_str() {
         let val_str = val.as_str().unwrap();
         match key_name {
             "basePath" => {
-                let path_parse = PathBuf::from_str(val_str);
-                if path_parse.is_err() {
-                    eprintln!("gclone config error: Unrecognizable path: {}", val_str);
-                } else {
-                    conf.base_path = path_parse.unwrap();
-                }
+                conf.base_path = expand_path(val_str)
+                    .map_err(|err| Error::Config(format!("basePath: {}", err)))?;
             }
             "defaultDomain" => {
                 conf.default_domain = val_str.to_string();
             }
+            "layout" => {
+                conf.layout = Layout::parse(val_str).map_err(Error::Config)?;
+            }
+            "localDomain" => {
+                conf.local_domain = val_str.to_string();
+            }
             _ => {}
         }
+    } else if val.is_table() && key_name == "localPaths" {
+        for (local_path, domain) in val.as_table().unwrap() {
+            let domain = domain.as_str().ok_or_else(|| {
+                Error::Config(format!(
+                    "localPaths entry for {} must be a string",
+                    local_path
+                ))
+            })?;
+            let local_path = expand_path(local_path)
+                .map_err(|err| Error::Config(format!("localPaths: {}", err)))?;
+            conf.local_paths.push((local_path, domain.to_string()));
+        }
+    } else if val.is_table() && key_name == "domains" {
+        for (domain, domain_val) in val.as_table().unwrap() {
+            let domain_table = domain_val
+                .as_table()
+                .ok_or_else(|| Error::Config(format!("domains.{} must be a table", domain)))?;
+            let domain_conf = conf.domains.entry(domain.clone()).or_default();
+            for (domain_key, domain_key_val) in domain_table {
+                parse_domain_value(domain, domain_conf, domain_key, domain_key_val)?;
+            }
+        }
+    } else if val.is_array() && key_name == "workspaces" {
+        for workspace_val in val.as_array().unwrap() {
+            let workspace = parse_workspace(workspace_val)
+                .map_err(|err| Error::Config(format!("workspaces: {}", err)))?;
+            conf.workspaces.push(workspace);
+        }
     } else if val.is_bool() && key_name == "defaultHttps" {
         conf.default_https = val.as_bool().unwrap();
+    } else if val.is_bool() && key_name == "verifyRemote" {
+        conf.verify_remote = val.as_bool().unwrap();
+    } else if val.is_bool() && key_name == "protocolFallback" {
+        conf.protocol_fallback = val.as_bool().unwrap();
     } else if val.is_integer() && key_name == "defaultDepth" {
         conf.default_search_depth = val.as_integer().unwrap() as usize;
     }
+    Ok(())
 }
 
-fn get_default_base_path() -> PathBuf {
-    let mut base_path = PathBuf::from("~");
-    let home_opt = dirs::home_dir();
-    if home_opt.is_some() {
-        base_path = home_opt.unwrap();
+fn parse_domain_value(
+    domain: &str,
+    domain_conf: &mut DomainConfig,
+    key_name: &str,
+    val: &Value,
+) -> Result<()> {
+    let invalid = |expected: &str| {
+        Error::Config(format!(
+            "domains.{}.{} must be {}",
+            domain, key_name, expected
+        ))
+    };
+    match key_name {
+        "sshUser" => {
+            let ssh_user = val.as_str().ok_or_else(|| invalid("a string"))?;
+            domain_conf.ssh_user = Some(ssh_user.to_string());
+        }
+        "layout" => {
+            let layout = val.as_str().ok_or_else(|| invalid("a string"))?;
+            domain_conf.layout = Some(Layout::parse(layout).map_err(Error::Config)?);
+        }
+        "https" => {
+            domain_conf.https = Some(val.as_bool().ok_or_else(|| invalid("a boolean"))?);
+        }
+        "depth" => {
+            let depth = val
+                .as_integer()
+                .filter(|depth| *depth >= 0)
+                .ok_or_else(|| invalid("a non-negative integer"))?;
+            domain_conf.search_depth = Some(depth as usize);
+        }
+        "sshPort" | "httpsPort" => {
+            let port = val
+                .as_integer()
+                .filter(|port| *port > 0 && *port <= i64::from(u16::MAX))
+                .ok_or_else(|| invalid("a port number"))?;
+            if key_name == "sshPort" {
+                domain_conf.ssh_port = Some(port as u16);
+            } else {
+                domain_conf.https_port = Some(port as u16);
+            }
+        }
+        "cloneArgs" => {
+            domain_conf.clone_args = parse_string_list(val)
+                .map_err(|err| Error::Config(format!("domains.{}.cloneArgs: {}", domain, err)))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_workspace(val: &Value) -> std::result::Result<Workspace, String> {
+    let table = val.as_table().ok_or("Each workspace must be a table")?;
+    let name = table
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or("Each workspace needs a name")?;
+    if name == DEFAULT_WORKSPACE_NAME {
+        return Err(format!("{} is a reserved workspace name", name));
     }
+    let base_path = table
+        .get("basePath")
+        .and_then(Value::as_str)
+        .ok_or(format!("Workspace {} needs a basePath", name))?;
+
+    Ok(Workspace {
+        name: name.to_string(),
+        base_path: expand_path(base_path)?,
+        domains: parse_patterns(table.get("domains"))?,
+        owners: parse_patterns(table.get("owners"))?,
+    })
+}
+
+fn parse_patterns(val: Option<&Value>) -> std::result::Result<Vec<Pattern>, String> {
+    let vals = match val {
+        Some(val) => val.as_array().ok_or("Patterns must be a list of strings")?,
+        None => return Ok(Vec::new()),
+    };
+    let mut patterns = Vec::new();
+    for pattern_val in vals {
+        let pattern_str = pattern_val
+            .as_str()
+            .ok_or("Patterns must be a list of strings")?;
+        let pattern = Pattern::new(pattern_str)
+            .map_err(|err| format!("Invalid pattern {}: {}", pattern_str, err.msg))?;
+        patterns.push(pattern);
+    }
+    Ok(patterns)
+}
+
+/// Parses a list of strings, like `cloneArgs`.
+fn parse_string_list(val: &Value) -> std::result::Result<Vec<String>, String> {
+    let vals = val.as_array().ok_or("Expected a list of strings")?;
+    vals.iter()
+        .map(|val| {
+            val.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Expected a list of strings".to_string())
+        })
+        .collect()
+}
+
+/// Expands a leading `~` as well as `$VAR` and `${VAR}` environment variables in
+/// a path from the config file. The expanded path must be absolute.
+fn expand_path(raw_path: &str) -> std::result::Result<PathBuf, String> {
+    let mut expanded = String::new();
+    let mut rest = raw_path;
+
+    if rest == "~" || rest.starts_with("~/") {
+        let home_dir = dirs::home_dir().ok_or("Unable to determine home directory")?;
+        expanded.push_str(
+            home_dir
+                .to_str()
+                .ok_or("Home directory is not valid unicode")?,
+        );
+        rest = &rest[1..];
+    }
+
+    while let Some(dollar_index) = rest.find('$') {
+        expanded.push_str(&rest[..dollar_index]);
+        rest = &rest[dollar_index + 1..];
+
+        let (var_name, remaining) = if rest.starts_with('{') {
+            match rest.find('}') {
+                Some(close_index) => (&rest[1..close_index], &rest[close_index + 1..]),
+                None => return Err(format!("Unterminated variable in path: {}", raw_path)),
+            }
+        } else {
+            let name_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..name_len], &rest[name_len..])
+        };
+
+        if var_name.is_empty() {
+            return Err(format!("Empty variable name in path: {}", raw_path));
+        }
+        match env::var(var_name) {
+            Ok(var_val) => expanded.push_str(&var_val),
+            Err(_) => return Err(format!("Environment variable {} is not set", var_name)),
+        }
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    let path = PathBuf::from(expanded);
+    if !path.is_absolute() {
+        return Err(format!("Path must be absolute: {}", raw_path));
+    }
+    Ok(path)
+}
+
+fn get_default_base_path() -> Option<PathBuf> {
+    let mut base_path = dirs::home_dir()?;
     base_path.push("Projects");
     base_path.push("git");
-    base_path
+    Some(base_path)
 }
 
 fn get_default_domain() -> &'static str {
     "github.com"
 }
 
+fn get_default_local_domain() -> &'static str {
+    "local"
+}
+
 fn get_default_is_https() -> bool {
     false
 }
@@ -141,3 +600,327 @@ fn get_default_is_https() -> bool {
 fn get_default_search_depth() -> usize {
     2
 }
+
+fn get_default_update_jobs() -> usize {
+    8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_path;
+    use super::parse_value;
+    use super::Config;
+    use crate::error::{Error, Result};
+    use crate::layout::Layout;
+    use std::path::PathBuf;
+    use toml::Value;
+
+    fn try_parse_config(contents: &str) -> Result<Config> {
+        let mut conf = Config::new();
+        let parsed_toml = contents.parse::<Value>().unwrap();
+        for (key, val) in parsed_toml.as_table().unwrap() {
+            parse_value(&mut conf, key, val)?;
+        }
+        Ok(conf)
+    }
+
+    fn parse_config(contents: &str) -> Config {
+        try_parse_config(contents).unwrap()
+    }
+
+    #[test]
+    fn test_domain_table() {
+        let conf = parse_config(
+            r#"
+            defaultHttps = false
+            defaultDepth = 2
+
+            [domains."gitlab.corp.example"]
+            https = true
+            sshUser = "gitlab"
+            depth = 4
+            sshPort = 2222
+            httpsPort = 8443
+            "#,
+        );
+        assert!(conf.get_is_https_for("gitlab.corp.example"));
+        assert_eq!(
+            conf.get_ssh_user("gitlab.corp.example"),
+            Some(&"gitlab".to_string())
+        );
+        assert_eq!(conf.get_search_depth("gitlab.corp.example"), 4);
+        assert_eq!(conf.get_ssh_port("gitlab.corp.example"), Some(2222));
+        assert_eq!(conf.get_https_port("gitlab.corp.example"), Some(8443));
+    }
+
+    #[test]
+    fn test_domain_fallback() {
+        let conf = parse_config(
+            r#"
+            defaultHttps = true
+            defaultDepth = 3
+
+            [domains."aur.archlinux.org"]
+            sshUser = "aur"
+            "#,
+        );
+        assert!(conf.get_is_https_for("aur.archlinux.org"));
+        assert_eq!(conf.get_domain_is_https("aur.archlinux.org"), None);
+        assert_eq!(conf.get_search_depth("aur.archlinux.org"), 3);
+        assert_eq!(conf.get_search_depth("github.com"), 3);
+        assert_eq!(conf.get_ssh_user("github.com"), None);
+        assert_eq!(conf.get_ssh_port("aur.archlinux.org"), None);
+        assert_eq!(conf.get_https_port("aur.archlinux.org"), None);
+    }
+
+    #[test]
+    fn test_domain_layout() {
+        let conf = parse_config(
+            r#"
+            layout = "{domain}/{owner}--{repo}"
+
+            [domains."github.com"]
+            layout = "{owner}/{repo}"
+            "#,
+        );
+        assert_eq!(
+            conf.get_layout("github.com"),
+            &Layout::parse("{owner}/{repo}").unwrap()
+        );
+        assert_eq!(
+            conf.get_layout("gitlab.com"),
+            &Layout::parse("{domain}/{owner}--{repo}").unwrap()
+        );
+        assert_eq!(conf.get_layout_domains(), vec!["github.com"]);
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        assert!(try_parse_config(r#"layout = "{domain}/{owner}""#).is_err());
+    }
+
+    #[test]
+    fn test_workspaces() {
+        let conf = parse_config(
+            r#"
+            basePath = "/home/user/oss"
+
+            [[workspaces]]
+            name = "work"
+            basePath = "/home/user/work"
+            domains = ["github.com"]
+            owners = ["acme-*"]
+
+            [[workspaces]]
+            name = "corp"
+            basePath = "/home/user/work"
+            domains = ["*.corp.example"]
+            "#,
+        );
+        let oss = PathBuf::from("/home/user/oss");
+        let work = PathBuf::from("/home/user/work");
+        assert_eq!(
+            conf.get_base_path_for("github.com", "acme-corp/tool"),
+            &work
+        );
+        assert_eq!(conf.get_base_path_for("github.com", "allonsy/gclone"), &oss);
+        assert_eq!(conf.get_base_path_for("gitlab.com", "acme-corp/tool"), &oss);
+        assert_eq!(
+            conf.get_base_path_for("gitlab.corp.example", "group/sub/repo"),
+            &work
+        );
+        assert_eq!(conf.get_workspace_base_path("work"), Some(&work));
+        assert_eq!(conf.get_workspace_base_path("default"), Some(&oss));
+        assert_eq!(conf.get_workspace_base_path("missing"), None);
+        assert_eq!(conf.get_all_base_paths(), vec![&oss, &work]);
+    }
+
+    #[test]
+    fn test_workspace_nested_owner() {
+        let conf = parse_config(
+            r#"
+            basePath = "/home/user/oss"
+
+            [[workspaces]]
+            name = "work"
+            basePath = "/home/user/work"
+            owners = ["acme"]
+            "#,
+        );
+        let work = PathBuf::from("/home/user/work");
+        assert_eq!(conf.get_base_path_for("gitlab.com", "acme/sub/repo"), &work);
+        assert_eq!(
+            conf.get_base_path_for("gitlab.com", "acme"),
+            &PathBuf::from("/home/user/oss")
+        );
+    }
+
+    #[test]
+    fn test_invalid_workspaces() {
+        let invalid_workspaces = [
+            r#"
+            [[workspaces]]
+            name = "default"
+            basePath = "/home/user/work"
+            "#,
+            r#"
+            [[workspaces]]
+            name = "nobase"
+            "#,
+            r#"
+            [[workspaces]]
+            name = "relative"
+            basePath = "work"
+            "#,
+            r#"
+            [[workspaces]]
+            name = "pattern"
+            basePath = "/home/user/work"
+            owners = ["acme-[*"]
+            "#,
+        ];
+        for contents in &invalid_workspaces {
+            assert!(try_parse_config(contents).is_err());
+        }
+    }
+
+    #[test]
+    fn test_domain_invalid_port() {
+        let contents = r#"
+            [domains."github.com"]
+            sshPort = 70000
+            "#;
+        assert!(try_parse_config(contents).is_err());
+        let contents = r#"
+            [domains."github.com"]
+            httpsPort = 0
+            "#;
+        assert!(try_parse_config(contents).is_err());
+    }
+
+    #[test]
+    fn test_domain_wrong_types() {
+        let invalid_values = [
+            ("https", r#""yes""#, "a boolean"),
+            ("depth", r#""4""#, "a non-negative integer"),
+            ("depth", "-1", "a non-negative integer"),
+            ("sshPort", r#""22""#, "a port number"),
+            ("httpsPort", "true", "a port number"),
+            ("sshUser", "1", "a string"),
+            ("layout", "false", "a string"),
+        ];
+        for (key, value, expected) in &invalid_values {
+            let contents = format!("[domains.\"github.com\"]\n{} = {}", key, value);
+            match try_parse_config(&contents) {
+                Err(Error::Config(message)) => assert_eq!(
+                    message,
+                    format!("domains.github.com.{} must be {}", key, expected)
+                ),
+                _ => panic!("{} = {} was accepted", key, value),
+            }
+        }
+    }
+
+    #[test]
+    fn test_clone_args() {
+        let conf = parse_config(
+            r#"
+            defaultCloneArgs = ["--recurse-submodules"]
+
+            [domains."git.corp.example"]
+            cloneArgs = ["--filter=blob:none", "--origin", "upstream"]
+            "#,
+        );
+        assert_eq!(
+            conf.get_clone_args("github.com"),
+            vec!["--recurse-submodules"]
+        );
+        assert_eq!(
+            conf.get_clone_args("git.corp.example"),
+            vec![
+                "--recurse-submodules",
+                "--filter=blob:none",
+                "--origin",
+                "upstream"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_remote() {
+        assert!(!parse_config("").get_verify_remote());
+        assert!(parse_config("verifyRemote = true").get_verify_remote());
+    }
+
+    #[test]
+    fn test_protocol_fallback() {
+        assert!(!parse_config("").get_protocol_fallback());
+        assert!(parse_config("protocolFallback = true").get_protocol_fallback());
+    }
+
+    #[test]
+    fn test_update_jobs() {
+        assert_eq!(parse_config("").get_update_jobs(), 8);
+        assert_eq!(parse_config("updateJobs = 3").get_update_jobs(), 3);
+        assert!(try_parse_config("updateJobs = 0").is_err());
+        assert!(try_parse_config(r#"updateJobs = "4""#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_clone_args() {
+        assert!(try_parse_config(r#"defaultCloneArgs = "--depth 1""#).is_err());
+        assert!(try_parse_config(r#"defaultCloneArgs = [["--depth", "1"]]"#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_base_path() {
+        assert!(try_parse_config(r#"basePath = "Projects/git""#).is_err());
+    }
+
+    #[test]
+    fn test_expand_absolute_path() {
+        assert_eq!(
+            expand_path("/home/user/git"),
+            Ok(PathBuf::from("/home/user/git"))
+        );
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let mut expected_path = dirs::home_dir().unwrap();
+        expected_path.push("Projects/git");
+        assert_eq!(expand_path("~/Projects/git"), Ok(expected_path));
+        assert_eq!(expand_path("~"), Ok(dirs::home_dir().unwrap()));
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        std::env::set_var("GCLONE_TEST_EXPAND_DIR", "/srv/gclone");
+        assert_eq!(
+            expand_path("$GCLONE_TEST_EXPAND_DIR/git"),
+            Ok(PathBuf::from("/srv/gclone/git"))
+        );
+        assert_eq!(
+            expand_path("${GCLONE_TEST_EXPAND_DIR}-trees/git"),
+            Ok(PathBuf::from("/srv/gclone-trees/git"))
+        );
+    }
+
+    #[test]
+    fn test_expand_unset_env_var() {
+        assert!(expand_path("$GCLONE_TEST_UNSET_VAR/git").is_err());
+        assert!(expand_path("${GCLONE_TEST_UNSET_VAR").is_err());
+    }
+
+    #[test]
+    fn test_expand_relative_path() {
+        assert!(expand_path("Projects/git").is_err());
+        assert!(expand_path("~user/git").is_err());
+    }
+
+    #[test]
+    fn test_base_path_expansion() {
+        let conf = parse_config(r#"basePath = "~/src""#);
+        assert_eq!(conf.get_base_path(), &dirs::home_dir().unwrap().join("src"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line usage.
+    Usage(String),
+    /// A repo url or name that can't be understood.
+    Parse(String),
+    /// An unreadable or invalid config file.
+    Config(String),
+    /// A filesystem operation that failed.
+    Fs(String, io::Error),
+    /// A git invocation that failed.
+    Git(String),
+    /// A git invocation that was stopped by SIGINT or SIGTERM.
+    Interrupted,
+    /// A clone target that exists but isn't a git repository.
+    Occupied(String),
+    /// A clone target that is a git repository of another remote.
+    RemoteMismatch(String),
+    /// Signal handlers that couldn't be set up.
+    Signal(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(msg)
+            | Error::Parse(msg)
+            | Error::Git(msg)
+            | Error::Occupied(msg)
+            | Error::RemoteMismatch(msg)
+            | Error::Signal(msg) => write!(f, "{}", msg),
+            Error::Config(msg) => write!(f, "gclone config error: {}", msg),
+            Error::Fs(msg, err) => write!(f, "{}: {}", msg, err),
+            Error::Interrupted => write!(f, "Interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Fs(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::interrupt::InterruptGuard;
+use gclone::{Error, Result};
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// What a failed git invocation ran into, guessed from its stderr.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FailureKind {
+    NotFound,
+    Auth,
+    HostKey,
+    Unreachable,
+    Other,
+}
+
+impl FailureKind {
+    pub fn from_stderr(stderr: &str) -> FailureKind {
+        let stderr = stderr.to_lowercase();
+        let contains_any = |needles: &[&str]| needles.iter().any(|needle| stderr.contains(needle));
+
+        if contains_any(&["host key verification failed"]) {
+            FailureKind::HostKey
+        } else if contains_any(&[
+            "repository not found",
+            "could not be found",
+            "' not found",
+            "does not appear to be a git repository",
+            "does not exist",
+            "error: 404",
+        ]) {
+            FailureKind::NotFound
+        } else if contains_any(&[
+            "permission denied",
+            "authentication failed",
+            "could not read username",
+            "could not read password",
+            "terminal prompts disabled",
+            "error: 403",
+        ]) {
+            FailureKind::Auth
+        } else if contains_any(&[
+            "could not resolve host",
+            "could not resolve hostname",
+            "name or service not known",
+            "connection refused",
+            "connection timed out",
+            "network is unreachable",
+        ]) {
+            FailureKind::Unreachable
+        } else {
+            FailureKind::Other
+        }
+    }
+}
+
+/// A git invocation against a remote that exited unsuccessfully.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+    url: String,
+    // Only kept when git's stderr wasn't already shown to the user.
+    stderr: Option<String>,
+}
+
+impl Failure {
+    /// Whether the other protocol could get past this failure, which is the
+    /// case when ssh keys or https credentials are missing.
+    pub fn is_protocol_specific(&self) -> bool {
+        self.kind == FailureKind::Auth || self.kind == FailureKind::HostKey
+    }
+
+    pub fn into_error(self) -> Error {
+        Error::Git(self.to_string())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FailureKind::NotFound => write!(f, "Repository not found: {}", self.url),
+            FailureKind::Auth => write!(
+                f,
+                "Authentication failed for {} (the repository may also not exist or be private)",
+                self.url
+            ),
+            FailureKind::HostKey => write!(f, "Host key verification failed for {}", self.url),
+            FailureKind::Unreachable => write!(f, "Unable to reach the host of {}", self.url),
+            FailureKind::Other => match &self.stderr {
+                Some(stderr) if !stderr.trim().is_empty() => {
+                    write!(f, "git failed for {}:\n{}", self.url, stderr.trim_end())
+                }
+                Some(_) => write!(f, "Unable to access {}", self.url),
+                None => write!(f, "Git clone process errored out!"),
+            },
+        }
+    }
+}
+
+/// Checks that `url` points to a repository we can read, before anything is
+/// created on disk.
+pub fn check_remote(url: &str) -> Result<std::result::Result<(), Failure>> {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg(url)
+        .arg("HEAD")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| Error::Git(format!("Failed to spawn git ls-remote process: {}", err)))?;
+    if output.status.success() {
+        return Ok(Ok(()));
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    Ok(Err(Failure {
+        kind: FailureKind::from_stderr(&stderr),
+        url: url.to_string(),
+        stderr: Some(stderr),
+    }))
+}
+
+/// Adds the remote `name` pointing to `url` to the repo at `dir`.
+pub fn add_remote(dir: &Path, name: &str, url: &str) -> Result<()> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["remote", "add", name, url])
+        .stdout(Stdio::from(io::stderr()))
+        .status()
+        .map_err(|err| Error::Git(format!("Failed to spawn git remote process: {}", err)))?;
+    if !status.success() {
+        return Err(Error::Git(format!(
+            "Unable to add the remote {} to {}",
+            name,
+            dir.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `git clone`, stopping git if `interrupt_guard` records a signal.
+pub fn clone(
+    interrupt_guard: &InterruptGuard,
+    url: &str,
+    clone_dir: &Path,
+    git_args: &[String],
+) -> Result<std::result::Result<(), Failure>> {
+    let mut clone_command = Command::new("git");
+    clone_command.arg("clone");
+    // git only reports progress on a terminal, which its stderr no longer is.
+    let quiet = git_args.iter().any(|arg| arg == "-q" || arg == "--quiet");
+    if io::stderr().is_terminal() && !quiet {
+        clone_command.arg("--progress");
+    }
+    clone_command.args(git_args).arg(url).arg(clone_dir);
+    run(&mut clone_command, url, interrupt_guard, true)
+}
+
+/// What `update` did to a repo that it didn't fail to update.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Updated {
+    Fetched,
+    FastForwarded,
+    /// The current branch couldn't be fast-forwarded as it has no upstream.
+    NoUpstream,
+}
+
+/// Fetches every remote of the repo at `dir`, and fast-forwards its current
+/// branch to its upstream when `pull` is set. `name` is only used to describe
+/// failures.
+pub fn update(
+    interrupt_guard: &InterruptGuard,
+    dir: &Path,
+    name: &str,
+    pull: bool,
+) -> Result<std::result::Result<Updated, Failure>> {
+    let mut fetch_command = Command::new("git");
+    fetch_command.arg("-C").arg(dir).args(["fetch", "--all"]);
+    if let Err(failure) = run(&mut fetch_command, name, interrupt_guard, false)? {
+        return Ok(Err(failure));
+    }
+    if !pull {
+        return Ok(Ok(Updated::Fetched));
+    }
+    if !has_upstream(dir)? {
+        return Ok(Ok(Updated::NoUpstream));
+    }
+    // The remotes were just fetched, so there is no need for `git pull`.
+    let mut merge_command = Command::new("git");
+    merge_command
+        .arg("-C")
+        .arg(dir)
+        .args(["merge", "--ff-only", "@{upstream}"]);
+    Ok(run(&mut merge_command, name, interrupt_guard, false)?.map(|()| Updated::FastForwarded))
+}
+
+/// Whether the current branch of the repo at `dir` has an upstream branch.
+fn has_upstream(dir: &Path) -> Result<bool> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--verify", "--quiet", "@{upstream}"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|err| Error::Git(format!("Failed to spawn git rev-parse process: {}", err)))?;
+    Ok(status.success())
+}
+
+/// Runs git, stopping it if `interrupt_guard` records a signal.
+///
+/// An interactive git may prompt for credentials and has its output passed
+/// through to our stderr. Otherwise git runs silently and without prompts,
+/// not even ssh ones on the terminal. git's stderr is kept either way, to tell
+/// what went wrong when it fails.
+fn run(
+    command: &mut Command,
+    url: &str,
+    interrupt_guard: &InterruptGuard,
+    interactive: bool,
+) -> Result<std::result::Result<(), Failure>> {
+    if interactive {
+        command.stdout(Stdio::from(io::stderr()));
+    } else {
+        // ssh reads passphrases and host key answers from the terminal itself.
+        let ssh_command = match env::var("GIT_SSH_COMMAND") {
+            Ok(ssh_command) if !ssh_command.is_empty() => ssh_command,
+            _ => "ssh".to_string(),
+        };
+        command
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env(
+                "GIT_SSH_COMMAND",
+                format!("{} -o BatchMode=yes", ssh_command),
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+    }
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::Git(format!("Failed to spawn git process: {}", err)))?;
+    let stderr_reader = child.stderr.take().map(|mut child_stderr| {
+        thread::spawn(move || {
+            let mut stderr = Vec::new();
+            let mut buf = [0; 4096];
+            while let Ok(read) = child_stderr.read(&mut buf) {
+                if read == 0 {
+                    break;
+                }
+                if interactive {
+                    let _ = io::stderr().write_all(&buf[..read]);
+                }
+                stderr.extend_from_slice(&buf[..read]);
+            }
+            String::from_utf8_lossy(&stderr).into_owned()
+        })
+    });
+
+    let status = loop {
+        let status = child
+            .try_wait()
+            .map_err(|err| Error::Git(format!("Unable to find child process: {}", err)))?;
+        if let Some(status) = status {
+            break status;
+        }
+        // git gets Ctrl-C too, but SIGTERM only reaches us.
+        if interrupt_guard.is_interrupted() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::Interrupted);
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let stderr = stderr_reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+    if interrupt_guard.is_interrupted() {
+        return Err(Error::Interrupted);
+    }
+    if !status.success() {
+        return Ok(Err(Failure {
+            kind: FailureKind::from_stderr(&stderr),
+            url: url.to_string(),
+            stderr: if interactive { None } else { Some(stderr) },
+        }));
+    }
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FailureKind, Updated};
+    use crate::interrupt::InterruptGuard;
+    use crate::test_util::get_test_dir;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=gclone",
+                "-c",
+                "user.email=gclone@example.org",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_update_fast_forwards_to_upstream() {
+        let test_dir = get_test_dir("git-update");
+        git(&test_dir, &["init", "-q", "--bare", "source.git"]);
+        git(&test_dir, &["clone", "-q", "source.git", "work"]);
+        let work_dir = test_dir.join("work");
+        git(&work_dir, &["commit", "-q", "--allow-empty", "-m", "first"]);
+        git(&work_dir, &["push", "-q", "origin", "HEAD"]);
+        git(&test_dir, &["clone", "-q", "source.git", "clone"]);
+        git(
+            &work_dir,
+            &["commit", "-q", "--allow-empty", "-m", "second"],
+        );
+        git(&work_dir, &["push", "-q", "origin", "HEAD"]);
+
+        let clone_dir = test_dir.join("clone");
+        let guard = InterruptGuard::new().unwrap();
+        let updated = super::update(&guard, &clone_dir, "clone", true).unwrap();
+        assert_eq!(updated.unwrap(), Updated::FastForwarded);
+        assert_eq!(
+            git(&clone_dir, &["rev-parse", "HEAD"]),
+            git(&work_dir, &["rev-parse", "HEAD"])
+        );
+
+        git(&clone_dir, &["checkout", "-q", "-b", "local"]);
+        let updated = super::update(&guard, &clone_dir, "clone", true).unwrap();
+        assert_eq!(updated.unwrap(), Updated::NoUpstream);
+        let updated = super::update(&guard, &clone_dir, "clone", false).unwrap();
+        assert_eq!(updated.unwrap(), Updated::Fetched);
+        std::fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_not_found_failures() {
+        let github_ssh = "ERROR: Repository not found.\n\
+                          fatal: Could not read from remote repository.\n\n\
+                          Please make sure you have the correct access rights\n\
+                          and the repository exists.";
+        assert_eq!(FailureKind::from_stderr(github_ssh), FailureKind::NotFound);
+        let local = "fatal: '/srv/missing' does not appear to be a git repository";
+        assert_eq!(FailureKind::from_stderr(local), FailureKind::NotFound);
+        let gitlab_https = "remote: The project you were looking for could not be found.\n\
+                            fatal: repository 'https://gitlab.com/a/b.git/' not found";
+        assert_eq!(
+            FailureKind::from_stderr(gitlab_https),
+            FailureKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_auth_failures() {
+        let ssh = "git@github.com: Permission denied (publickey).\n\
+                   fatal: Could not read from remote repository.";
+        assert_eq!(FailureKind::from_stderr(ssh), FailureKind::Auth);
+        let https = "fatal: could not read Username for 'https://github.com': \
+                     terminal prompts disabled";
+        assert_eq!(FailureKind::from_stderr(https), FailureKind::Auth);
+    }
+
+    #[test]
+    fn test_host_failures() {
+        let host_key = "Host key verification failed.\n\
+                        fatal: Could not read from remote repository.";
+        assert_eq!(FailureKind::from_stderr(host_key), FailureKind::HostKey);
+        let dns = "ssh: Could not resolve hostname gitub.com: Name or service not known";
+        assert_eq!(FailureKind::from_stderr(dns), FailureKind::Unreachable);
+        assert_eq!(
+            FailureKind::from_stderr("fatal: early EOF"),
+            FailureKind::Other
+        );
+    }
+}
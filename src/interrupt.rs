@@ -0,0 +1,125 @@
+use gclone::{Error, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::SigId;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of open guards, along with the flag that restores the default
+/// action of SIGINT and SIGTERM once none is open. signal-hook can't
+/// uninstall its handler, so the default action is emulated instead.
+static OPEN_GUARDS: Mutex<Option<(usize, Arc<AtomicBool>)>> = Mutex::new(None);
+
+/// Records SIGINT and SIGTERM instead of being killed by them, for as long as
+/// the guard is alive, so that whoever runs git can stop it and clean up.
+pub struct InterruptGuard {
+    interrupted: Arc<AtomicBool>,
+    signal_ids: Vec<SigId>,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Result<InterruptGuard> {
+        let register_error =
+            |err| Error::Signal(format!("Unable to register signal handler: {}", err));
+        {
+            let mut open_guards = OPEN_GUARDS.lock().unwrap_or_else(|err| err.into_inner());
+            match &mut *open_guards {
+                Some((count, unguarded)) => {
+                    *count += 1;
+                    unguarded.store(false, Ordering::SeqCst);
+                }
+                None => {
+                    let unguarded = Arc::new(AtomicBool::new(false));
+                    for signal in [SIGINT, SIGTERM] {
+                        signal_hook::flag::register_conditional_default(
+                            signal,
+                            Arc::clone(&unguarded),
+                        )
+                        .map_err(register_error)?;
+                    }
+                    *open_guards = Some((1, unguarded));
+                }
+            }
+        }
+
+        // Dropping the guard early releases the count taken above.
+        let mut guard = InterruptGuard {
+            interrupted: Arc::new(AtomicBool::new(false)),
+            signal_ids: Vec::new(),
+        };
+        for signal in [SIGINT, SIGTERM] {
+            let signal_id = signal_hook::flag::register(signal, Arc::clone(&guard.interrupted))
+                .map_err(register_error)?;
+            guard.signal_ids.push(signal_id);
+        }
+        Ok(guard)
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        for signal_id in self.signal_ids.drain(..) {
+            signal_hook::low_level::unregister(signal_id);
+        }
+        let mut open_guards = OPEN_GUARDS.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((count, unguarded)) = &mut *open_guards {
+            *count -= 1;
+            if *count == 0 {
+                unguarded.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InterruptGuard;
+    use signal_hook::consts::SIGTERM;
+    use std::env;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, ExitStatus};
+
+    static CHILD_VAR_NAME: &str = "GCLONE_TEST_INTERRUPT_CHILD";
+
+    fn is_child() -> bool {
+        env::var_os(CHILD_VAR_NAME).is_some()
+    }
+
+    /// Runs the test `name` alone in a child process. Signals raised in the
+    /// test process would reach the guards of every test running alongside.
+    fn run_in_child(name: &str) -> ExitStatus {
+        Command::new(env::current_exe().unwrap())
+            .args([&format!("interrupt::tests::{}", name), "--exact"])
+            .env(CHILD_VAR_NAME, "1")
+            .output()
+            .unwrap()
+            .status
+    }
+
+    #[test]
+    fn test_signal_is_recorded() {
+        if is_child() {
+            let guard = InterruptGuard::new().unwrap();
+            assert!(!guard.is_interrupted());
+            signal_hook::low_level::raise(SIGTERM).unwrap();
+            assert!(guard.is_interrupted());
+            return;
+        }
+        assert!(run_in_child("test_signal_is_recorded").success());
+    }
+
+    #[test]
+    fn test_default_action_is_restored() {
+        if is_child() {
+            drop(InterruptGuard::new().unwrap());
+            signal_hook::low_level::raise(SIGTERM).unwrap();
+            std::thread::sleep(std::time::Duration::from_secs(5));
+            return;
+        }
+        let status = run_in_child("test_default_action_is_restored");
+        assert_eq!(status.signal(), Some(SIGTERM));
+    }
+}
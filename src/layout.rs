@@ -0,0 +1,369 @@
+use std::path::PathBuf;
+
+static DEFAULT_LAYOUT: &str = "{domain}/{path}";
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Placeholder {
+    Domain,
+    Owner,
+    Repo,
+    Path,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Token {
+    Literal(String),
+    Var(Placeholder),
+}
+
+/// A directory layout template like `{domain}/{owner}--{repo}`.
+///
+/// The template is split in two: the root, which only depends on the domain
+/// (`{domain}` above) and the tail, which depends on the repo path
+/// (`{owner}--{repo}` above). Completion walks the root of each domain and
+/// maps the directories it finds back to repo paths through the tail.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Layout {
+    template: String,
+    root: Vec<Token>,
+    tail: Vec<Token>,
+}
+
+impl Layout {
+    pub fn parse(template: &str) -> Result<Layout, String> {
+        let tokens = tokenize(template)?;
+        let has_repo = tokens.iter().any(|token| {
+            *token == Token::Var(Placeholder::Repo) || *token == Token::Var(Placeholder::Path)
+        });
+        if !has_repo {
+            return Err(format!(
+                "Layout must contain {{repo}} or {{path}}: {}",
+                template
+            ));
+        }
+        if template.starts_with('/') || template.split('/').any(|part| part == "..") {
+            return Err(format!("Layout must be a relative path: {}", template));
+        }
+
+        let tail_start = ["{owner}", "{repo}", "{path}"]
+            .iter()
+            .filter_map(|placeholder| template.find(placeholder))
+            .min()
+            .unwrap();
+        let (root, tail) = match template[..tail_start].rfind('/') {
+            Some(slash_index) => (&template[..slash_index], &template[slash_index + 1..]),
+            None => ("", template),
+        };
+
+        Ok(Layout {
+            template: template.to_string(),
+            root: tokenize(root)?,
+            tail: tokenize(tail)?,
+        })
+    }
+
+    /// Renders the directory of a repo relative to the base path.
+    pub fn render(&self, domain: &str, sub_path: &str) -> PathBuf {
+        let mut path = self.get_root(domain);
+        path.push(render_tokens(&self.tail, domain, sub_path));
+        path
+    }
+
+    /// Renders the directory (relative to the base path) under which all repos
+    /// of `domain` are placed.
+    pub fn get_root(&self, domain: &str) -> PathBuf {
+        render_tokens(&self.root, domain, "")
+    }
+
+    /// Returns the domain whose root is `root_path`, if the root of this layout
+    /// depends on the domain.
+    pub fn get_root_domain(&self, root_path: &str) -> Option<String> {
+        if !self.root.contains(&Token::Var(Placeholder::Domain)) {
+            return None;
+        }
+        let mut captures = Vec::new();
+        match match_tokens(&self.root, root_path, 0, None, &mut captures) {
+            Some(end) if end == root_path.len() => captures
+                .iter()
+                .find(|(placeholder, _)| *placeholder == Placeholder::Domain)
+                .map(|(_, domain)| domain.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns the glob pattern (relative to the base path) matching the roots
+    /// of every domain, if the root of this layout depends on the domain.
+    pub fn get_root_glob(&self) -> Option<String> {
+        if !self.root.contains(&Token::Var(Placeholder::Domain)) {
+            return None;
+        }
+        let mut pattern = String::new();
+        for token in &self.root {
+            match token {
+                Token::Literal(literal) => pattern.push_str(&glob::Pattern::escape(literal)),
+                Token::Var(_) => pattern.push('*'),
+            }
+        }
+        Some(pattern)
+    }
+
+    /// Maps a directory (given relative to the root of `domain`) back to the
+    /// repo path it belongs to. Directories that don't match the tail of the
+    /// layout, like the owner directory in `{owner}/{repo}`, are returned
+    /// unchanged.
+    pub fn get_sub_path(&self, domain: &str, rel_path: &str) -> String {
+        let mut captures = Vec::new();
+        let end = match match_tokens(&self.tail, rel_path, 0, Some(domain), &mut captures) {
+            Some(end) => end,
+            None => return rel_path.to_string(),
+        };
+
+        let capture = |wanted: Placeholder| {
+            captures
+                .iter()
+                .find(|(placeholder, _)| *placeholder == wanted)
+                .map(|(_, value)| *value)
+        };
+        let repo_path = match (capture(Placeholder::Path), capture(Placeholder::Owner)) {
+            (Some(path), _) => path.to_string(),
+            (None, Some(owner)) => format!("{}/{}", owner, capture(Placeholder::Repo).unwrap()),
+            (None, None) => capture(Placeholder::Repo).unwrap().to_string(),
+        };
+        format!("{}{}", repo_path, &rel_path[end..])
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::parse(DEFAULT_LAYOUT).unwrap()
+    }
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(open_index) = rest.find('{') {
+        if open_index > 0 {
+            tokens.push(Token::Literal(rest[..open_index].to_string()));
+        }
+        let close_index = match rest.find('}') {
+            Some(close_index) if close_index > open_index => close_index,
+            _ => return Err(format!("Unterminated placeholder in layout: {}", template)),
+        };
+        let placeholder = match &rest[open_index + 1..close_index] {
+            "domain" => Placeholder::Domain,
+            "owner" => Placeholder::Owner,
+            "repo" => Placeholder::Repo,
+            "path" => Placeholder::Path,
+            unknown => return Err(format!("Unknown layout placeholder: {{{}}}", unknown)),
+        };
+        tokens.push(Token::Var(placeholder));
+        rest = &rest[close_index + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Literal(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+fn render_tokens(tokens: &[Token], domain: &str, sub_path: &str) -> PathBuf {
+    let components: Vec<&str> = sub_path.split('/').filter(|c| !c.is_empty()).collect();
+    let (owner, repo) = match components.split_last() {
+        Some((repo, owner)) => (
+            owner.join("/"),
+            repo.strip_suffix(".git").unwrap_or(repo).to_string(),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let path = if owner.is_empty() {
+        repo.clone()
+    } else {
+        format!("{}/{}", owner, repo)
+    };
+
+    let mut rendered = String::new();
+    for token in tokens {
+        match token {
+            Token::Literal(literal) => rendered.push_str(literal),
+            Token::Var(Placeholder::Domain) => rendered.push_str(domain),
+            Token::Var(Placeholder::Owner) => rendered.push_str(&owner),
+            Token::Var(Placeholder::Repo) => rendered.push_str(&repo),
+            Token::Var(Placeholder::Path) => rendered.push_str(&path),
+        }
+    }
+
+    rendered.split('/').filter(|c| !c.is_empty()).collect()
+}
+
+/// Matches `tokens` against the start of `input[pos..]`, preferring the
+/// shortest values for placeholders. A match must end at the end of the input
+/// or right before a `/`. Returns the index where the match ended.
+fn match_tokens<'a>(
+    tokens: &[Token],
+    input: &'a str,
+    pos: usize,
+    domain: Option<&str>,
+    captures: &mut Vec<(Placeholder, &'a str)>,
+) -> Option<usize> {
+    let (token, rest_tokens) = match tokens.split_first() {
+        Some(split) => split,
+        None => {
+            let at_boundary = pos == input.len() || input[pos..].starts_with('/');
+            return if at_boundary { Some(pos) } else { None };
+        }
+    };
+
+    match token {
+        Token::Literal(literal) => {
+            if input[pos..].starts_with(literal.as_str()) {
+                match_tokens(rest_tokens, input, pos + literal.len(), domain, captures)
+            } else {
+                None
+            }
+        }
+        Token::Var(placeholder) => {
+            let may_contain_slash =
+                *placeholder == Placeholder::Owner || *placeholder == Placeholder::Path;
+            let ends = input[pos..]
+                .char_indices()
+                .skip(1)
+                .map(|(index, _)| pos + index)
+                .chain(std::iter::once(input.len()));
+            for end in ends {
+                let value = &input[pos..end];
+                if value.is_empty() || value.starts_with('/') || value.ends_with('/') {
+                    continue;
+                }
+                if !may_contain_slash && value.contains('/') {
+                    break;
+                }
+                if *placeholder == Placeholder::Domain && domain.is_some_and(|d| d != value) {
+                    continue;
+                }
+
+                captures.push((*placeholder, value));
+                if let Some(match_end) = match_tokens(rest_tokens, input, end, domain, captures) {
+                    return Some(match_end);
+                }
+                captures.pop();
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_default_layout_render() {
+        let layout = Layout::default();
+        assert_eq!(
+            layout.render("github.com", "user/repo.git"),
+            PathBuf::from("github.com/user/repo")
+        );
+        assert_eq!(
+            layout.render("github.com", "user/repo.git.git"),
+            PathBuf::from("github.com/user/repo.git")
+        );
+    }
+
+    #[test]
+    fn test_owner_repo_layout_render() {
+        let layout = Layout::parse("{owner}/{repo}").unwrap();
+        assert_eq!(
+            layout.render("github.com", "user/repo"),
+            PathBuf::from("user/repo")
+        );
+        assert_eq!(layout.render("github.com", "repo"), PathBuf::from("repo"));
+    }
+
+    #[test]
+    fn test_flat_layout_render() {
+        let layout = Layout::parse("{domain}/{owner}--{repo}").unwrap();
+        assert_eq!(
+            layout.render("gitlab.com", "group/sub/repo.git"),
+            PathBuf::from("gitlab.com/group/sub--repo")
+        );
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        assert!(Layout::parse("{domain}/{owner}").is_err());
+        assert!(Layout::parse("{domain}/{name}").is_err());
+        assert!(Layout::parse("{domain}/{path").is_err());
+        assert!(Layout::parse("/{domain}/{path}").is_err());
+        assert!(Layout::parse("../{path}").is_err());
+    }
+
+    #[test]
+    fn test_layout_root() {
+        assert_eq!(
+            Layout::default().get_root("github.com"),
+            PathBuf::from("github.com")
+        );
+        assert_eq!(
+            Layout::parse("{owner}/{repo}")
+                .unwrap()
+                .get_root("github.com"),
+            PathBuf::new()
+        );
+        assert_eq!(
+            Layout::parse("src/{domain}-mirror/{owner}--{repo}")
+                .unwrap()
+                .get_root("gitlab.com"),
+            PathBuf::from("src/gitlab.com-mirror")
+        );
+    }
+
+    #[test]
+    fn test_layout_root_domain() {
+        let layout = Layout::parse("src/{domain}-mirror/{path}").unwrap();
+        assert_eq!(layout.get_root_glob(), Some("src/*-mirror".to_string()));
+        assert_eq!(
+            layout.get_root_domain("src/gitlab.com-mirror"),
+            Some("gitlab.com".to_string())
+        );
+        assert_eq!(layout.get_root_domain("src/gitlab.com"), None);
+        assert_eq!(
+            Layout::parse("{owner}/{repo}").unwrap().get_root_glob(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_default_layout_sub_path() {
+        let layout = Layout::default();
+        assert_eq!(layout.get_sub_path("github.com", "user"), "user");
+        assert_eq!(
+            layout.get_sub_path("github.com", "user/repo/src"),
+            "user/repo/src"
+        );
+    }
+
+    #[test]
+    fn test_flat_layout_sub_path() {
+        let layout = Layout::parse("{domain}/{owner}--{repo}").unwrap();
+        assert_eq!(layout.get_sub_path("gitlab.com", "user--repo"), "user/repo");
+        assert_eq!(
+            layout.get_sub_path("gitlab.com", "group/sub--repo/src"),
+            "group/sub/repo/src"
+        );
+        assert_eq!(layout.get_sub_path("gitlab.com", "stray"), "stray");
+    }
+
+    #[test]
+    fn test_domain_in_tail_sub_path() {
+        let layout = Layout::parse("{owner}/{domain}-{repo}").unwrap();
+        assert_eq!(
+            layout.get_sub_path("github.com", "user/github.com-repo"),
+            "user/repo"
+        );
+        assert_eq!(
+            layout.get_sub_path("gitlab.com", "user/github.com-repo"),
+            "user/github.com-repo"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! gclone's url normalization and tree layout, as used by the `gclone-bin`
+//! binary.
+//!
+//! ```
+//! use gclone::{Config, Repo};
+//!
+//! let conf = Config::default();
+//! let repo = Repo::parse("allonsy/gclone", &conf).unwrap();
+//! assert_eq!(repo.get_clone_url(), "git@github.com:allonsy/gclone");
+//! assert!(repo.get_fs_path(&conf).ends_with("github.com/allonsy/gclone"));
+//! ```
+
+mod completion;
+mod config;
+mod error;
+mod layout;
+mod remote;
+mod repo;
+mod repo_id;
+#[cfg(test)]
+mod test_util;
+mod tree;
+
+pub use completion::get_matches;
+pub use config::{get_config, Config};
+pub use error::{Error, Result};
+pub use layout::Layout;
+pub use remote::get_remotes;
+pub use repo::Repo;
+pub use repo_id::RepoId;
+pub use tree::find_repos;
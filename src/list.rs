@@ -0,0 +1,163 @@
+use crate::cli::{ListFormat, RepoFilter};
+use gclone::{Config, Error, Repo, RepoId, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Returns the clones of the tree, with their repo, that `filter` lets
+/// through.
+pub fn find_repos(conf: &Config, filter: &RepoFilter) -> Result<Vec<(PathBuf, Repo)>> {
+    let pattern = match &filter.pattern {
+        Some(pattern) => Some(
+            glob::Pattern::new(pattern)
+                .map_err(|err| Error::Usage(format!("Invalid pattern {}: {}", pattern, err)))?,
+        ),
+        None => None,
+    };
+
+    let repos = gclone::find_repos(conf)
+        .into_iter()
+        .filter(|(_, repo)| {
+            let id = repo.get_id(conf);
+            let domain_matches = filter
+                .domain
+                .as_ref()
+                .is_none_or(|domain| id.get_host() == domain.to_lowercase());
+            let owner_matches = filter.owner.as_ref().is_none_or(|owner| {
+                let owner = owner.trim_matches('/');
+                let owner_path = id
+                    .get_path()
+                    .rsplit_once('/')
+                    .map_or("", |(owner_path, _)| owner_path);
+                owner_path == owner || owner_path.starts_with(&format!("{}/", owner))
+            });
+            let pattern_matches = pattern.as_ref().is_none_or(|pattern| {
+                pattern.matches(&format!("{}/{}", id.get_host(), id.get_path()))
+            });
+            domain_matches && owner_matches && pattern_matches
+        })
+        .collect();
+    Ok(repos)
+}
+
+/// Warns about the repos cloned in more than one place.
+pub fn warn_duplicates(conf: &Config, repos: &[(PathBuf, Repo)]) {
+    let mut dirs_by_id: BTreeMap<RepoId, Vec<&PathBuf>> = BTreeMap::new();
+    for (dir, repo) in repos {
+        dirs_by_id.entry(repo.get_id(conf)).or_default().push(dir);
+    }
+    for (id, dirs) in dirs_by_id {
+        if dirs.len() > 1 {
+            let dirs: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+            eprintln!("warning: {} is cloned in {}", id, dirs.join(", "));
+        }
+    }
+}
+
+pub fn print_repos(conf: &Config, repos: &[(PathBuf, Repo)], format: ListFormat) {
+    if format == ListFormat::Json {
+        println!("{}", get_json(conf, repos));
+        return;
+    }
+    for (dir, repo) in repos {
+        match format {
+            ListFormat::Shorthand => println!("{}", repo.get_shorthand(conf)),
+            ListFormat::Path => println!("{}", dir.display()),
+            ListFormat::Url => println!("{}", repo.get_clone_url()),
+            ListFormat::Json => {}
+        }
+    }
+}
+
+fn get_json(conf: &Config, repos: &[(PathBuf, Repo)]) -> String {
+    let objects: Vec<String> = repos
+        .iter()
+        .map(|(dir, repo)| {
+            let optional_string = |value: Option<String>| match value {
+                Some(value) => get_json_string(&value),
+                None => "null".to_string(),
+            };
+            let fields = [
+                ("shorthand", get_json_string(&repo.get_shorthand(conf))),
+                ("domain", get_json_string(&repo.get_domain())),
+                ("path", get_json_string(&dir.display().to_string())),
+                ("url", get_json_string(&repo.get_clone_url())),
+                ("sshUrl", optional_string(repo.get_ssh_url(conf))),
+                ("httpsUrl", optional_string(repo.get_https_url(conf))),
+            ];
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("\"{}\": {}", name, value))
+                .collect();
+            format!("  {{{}}}", fields.join(", "))
+        })
+        .collect();
+    if objects.is_empty() {
+        "[]".to_string()
+    } else {
+        format!("[\n{}\n]", objects.join(",\n"))
+    }
+}
+
+fn get_json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for character in value.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                json.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_json, get_json_string};
+    use gclone::{Config, Repo};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(get_json_string("plain"), "\"plain\"");
+        assert_eq!(
+            get_json_string("a \"b\"\\c\n\u{1}"),
+            "\"a \\\"b\\\"\\\\c\\n\\u0001\""
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let conf = Config::default();
+        assert_eq!(get_json(&conf, &[]), "[]");
+        let repos = vec![
+            (
+                PathBuf::from("/git/github.com/allonsy/gclone"),
+                Repo::parse("allonsy/gclone", &conf).unwrap(),
+            ),
+            (
+                PathBuf::from("/git/local/srv/tool"),
+                Repo::parse("/srv/tool", &conf).unwrap(),
+            ),
+        ];
+        assert_eq!(
+            get_json(&conf, &repos),
+            "[\n  \
+             {\"shorthand\": \"allonsy/gclone\", \"domain\": \"github.com\", \
+             \"path\": \"/git/github.com/allonsy/gclone\", \
+             \"url\": \"git@github.com:allonsy/gclone\", \
+             \"sshUrl\": \"git@github.com:allonsy/gclone\", \
+             \"httpsUrl\": \"https://github.com/allonsy/gclone\"},\n  \
+             {\"shorthand\": \"local:srv/tool\", \"domain\": \"local\", \
+             \"path\": \"/git/local/srv/tool\", \"url\": \"/srv/tool\", \
+             \"sshUrl\": null, \"httpsUrl\": null}\n\
+             ]"
+        );
+    }
+}
@@ -1,175 +1,649 @@
-mod completion;
-mod config;
-mod repo;
+mod cli;
+mod git;
+mod interrupt;
+mod list;
+mod shell;
+#[cfg(test)]
+mod test_util;
+mod transaction;
+mod update;
 
+use cli::{Action, CloneOptions};
+use gclone::{Config, Error, Repo, Result};
 use std::env;
+use std::ffi::OsString;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process;
+use transaction::Transaction;
 
-static mut GLOBAL_ROLLBACK: Option<Vec<PathBuf>> = None;
-static mut STARTING_DIR: Option<PathBuf> = None;
+static CD_FILE_VAR_NAME: &str = "GCLONE_CD_FILE";
 
 fn main() {
     let starting_dir = env::current_dir();
-    if starting_dir.is_err() {
-        error_out("Unable to retrieve current working directory");
-    }
-    unsafe {
-        STARTING_DIR = Some(starting_dir.as_ref().unwrap().clone());
-    }
+    let result = match &starting_dir {
+        Ok(starting_dir) => run(starting_dir),
+        Err(err) => Err(Error::Fs(
+            "Unable to retrieve current working directory".to_string(),
+            std::io::Error::new(err.kind(), err.to_string()),
+        )),
+    };
 
-    let options = parse_args();
-    let repo = repo::Repo::parse(&options.url);
-    let full_path = repo.get_fs_path();
+    let starting_dir = starting_dir.ok();
+    let result = result
+        .and_then(|target_dir| write_cd_target(target_dir.as_deref(), starting_dir.as_deref()));
+    if let Err(err) = result {
+        error_out(&err, starting_dir.as_deref());
+    }
+}
 
-    if !options.nocd && full_path.exists() {
-        println!("{}", full_path.to_str().unwrap());
-        std::process::exit(0);
+/// Hands the directory to cd into over to the shell wrapper. When the wrapper
+/// names a file in `$GCLONE_CD_FILE`, the directory is written there (and
+/// nothing is written when there is nothing to cd into). Otherwise it is
+/// printed on stdout for older wrappers, which always cd into the output.
+fn write_cd_target(target_dir: Option<&Path>, starting_dir: Option<&Path>) -> Result<()> {
+    match env::var_os(CD_FILE_VAR_NAME) {
+        Some(cd_file) if !cd_file.is_empty() => match target_dir {
+            Some(target_dir) => std::fs::write(&cd_file, target_dir.as_os_str().as_encoded_bytes())
+                .map_err(|err| {
+                    Error::Fs(
+                        format!("Unable to write to {}", Path::new(&cd_file).display()),
+                        err,
+                    )
+                }),
+            None => Ok(()),
+        },
+        _ => {
+            if let Some(dir) = target_dir.or(starting_dir) {
+                println!("{}", dir.display());
+            }
+            Ok(())
+        }
     }
+}
 
-    if !options.local {
-        let target_dir = full_path.parent();
-        if target_dir.is_none() {
-            error_out("root repo cannot be used");
+fn run(starting_dir: &Path) -> Result<Option<PathBuf>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match cli::parse_args(&args)? {
+        Action::Clone(options) => options,
+        Action::Path { workspace, url } => {
+            let conf = gclone::get_config()?;
+            let repo = Repo::parse(&url, &conf)?;
+            print_and_exit(&get_full_path(&conf, &repo, workspace.as_deref())?.display())
+        }
+        Action::Which(path) => {
+            let path = path.map_or_else(|| starting_dir.to_path_buf(), PathBuf::from);
+            print_which(&path)?;
+            std::process::exit(0);
+        }
+        Action::List(filter, format) => {
+            let conf = gclone::get_config()?;
+            let repos = list::find_repos(&conf, &filter)?;
+            list::warn_duplicates(&conf, &repos);
+            list::print_repos(&conf, &repos, format);
+            std::process::exit(0);
+        }
+        Action::Update(options) => {
+            update::update(&gclone::get_config()?, &options)?;
+            std::process::exit(0);
+        }
+        Action::Complete(prefix) => {
+            print_matches(&prefix)?;
+            std::process::exit(0);
+        }
+        Action::Config(key) => {
+            print_config(key.as_deref())?;
+            std::process::exit(0);
+        }
+        Action::Init(shell) => {
+            print!("{}", shell::get_init_script(&shell)?);
+            std::process::exit(0);
         }
-        mkdir(target_dir.as_ref().unwrap());
-        let cwd_result = env::set_current_dir(target_dir.unwrap());
-        if cwd_result.is_err() {
-            error_out("Unable to cd to clone directory");
+        Action::Completions(shell) => {
+            print!("{}", shell::get_completions(&shell)?);
+            std::process::exit(0);
         }
+        Action::Help(command) => {
+            print!("{}", cli::get_help(command));
+            std::process::exit(0);
+        }
+        Action::Version => print_and_exit(&cli::get_version()),
+    };
+    clone(starting_dir, &options)
+}
+
+fn clone(starting_dir: &Path, options: &CloneOptions) -> Result<Option<PathBuf>> {
+    let conf = gclone::get_config()?;
+    let repo = Repo::parse(&options.url, &conf)?;
+    let base_path = get_base_path(&conf, &repo, options.workspace.as_deref())?;
+
+    let (base_path, clone_dir) = if options.local {
+        (starting_dir, starting_dir.join(repo.get_repo_name()))
+    } else {
+        (base_path, repo.get_fs_path_in(&conf, base_path))
+    };
+    if clone_dir.exists() {
+        let is_clone = check_existing_dir(&conf, &repo, &clone_dir, base_path, options)?;
+        if options.nocd {
+            if is_clone {
+                eprintln!(
+                    "{} is already cloned in {}",
+                    options.url,
+                    clone_dir.display()
+                );
+            }
+            return Ok(None);
+        }
+        return Ok(Some(clone_dir));
     }
-    clone_repo(&repo);
+    let parent_dir = clone_dir
+        .parent()
+        .ok_or_else(|| Error::Parse("root repo cannot be used".to_string()))?;
+    let fallback_url = if conf.get_protocol_fallback() {
+        repo.get_fallback_url(&conf)
+    } else {
+        None
+    };
+    let (url, fallback_url) = if conf.get_verify_remote() {
+        let url = with_fallback(
+            repo.get_clone_url(),
+            fallback_url.clone(),
+            git::check_remote,
+        )?;
+        // When only the fallback url could be read, cloning from the primary
+        // url is no use, so there is nothing left to retry with.
+        if url == repo.get_clone_url() {
+            (url, fallback_url)
+        } else {
+            (url, None)
+        }
+    } else {
+        (repo.get_clone_url(), fallback_url)
+    };
+    let mut transaction = Transaction::new()?;
+    transaction.create_dir_all(parent_dir)?;
+
+    let mut git_args = conf.get_clone_args(&repo.get_domain());
+    git_args.extend(options.git_args.iter().cloned());
+    clone_atomically(&mut transaction, url, fallback_url, &clone_dir, &git_args)?;
+    transaction.commit();
 
     if options.nocd {
-        println!("{}", starting_dir.unwrap().to_str().unwrap());
-    } else if options.local {
-        println!("{}", repo.get_repo_name());
+        Ok(None)
     } else {
-        println!("{}", full_path.to_str().unwrap());
+        Ok(Some(clone_dir))
     }
 }
 
-fn clone_repo(repo: &repo::Repo) {
-    let mut clone_command = Command::new("git");
-    eprintln!("using url: {}", repo.get_clone_url());
-    clone_command.arg("clone").arg(repo.get_clone_url());
-    let status = clone_command.spawn();
-    if status.is_err() {
-        error_out("Failed to spawn git clone process");
-    }
-    let status_code = status.unwrap().wait();
-    if status_code.is_err() {
-        error_out("Unable to find child process");
-    }
-    if !status_code.unwrap().success() {
-        error_out("Git clone process errored out!");
+/// Checks the existing `dir` that `repo` would be cloned into, so that cloning
+/// can be skipped. Returns whether `dir` is a clone of `repo`. Otherwise, it is
+/// a directory of the tree below `base_path` that `gclone` just cds into, like
+/// the directory of an owner or a directory inside a clone.
+fn check_existing_dir(
+    conf: &Config,
+    repo: &Repo,
+    dir: &Path,
+    base_path: &Path,
+    options: &CloneOptions,
+) -> Result<bool> {
+    if !dir.join(".git").exists() && is_tree_dir(dir, base_path) {
+        return Ok(false);
     }
+    check_existing_clone(conf, repo, dir, options)?;
+    Ok(true)
 }
 
-fn mkdir(path: &Path) {
-    let mut cur_path = path;
-    while !cur_path.exists() {
-        unsafe {
-            if GLOBAL_ROLLBACK.is_none() {
-                GLOBAL_ROLLBACK = Some(vec![PathBuf::from(cur_path)]);
-            } else {
-                GLOBAL_ROLLBACK
-                    .as_mut()
-                    .unwrap()
-                    .push(PathBuf::from(cur_path));
-            }
-        }
-        cur_path = cur_path.parent().unwrap();
-    }
-    let res = std::fs::create_dir_all(path);
-    if res.is_err() {
-        error_out("Unable to create directory structure");
-    }
+/// Whether `dir` is inside a clone below `base_path`, or holds clones.
+fn is_tree_dir(dir: &Path, base_path: &Path) -> bool {
+    let is_inside_clone = dir
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| ancestor.starts_with(base_path) && *ancestor != base_path)
+        .any(|ancestor| ancestor.join(".git").exists());
+    is_inside_clone || holds_clones(dir)
+}
+
+fn holds_clones(dir: &Path) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries.flatten().any(|entry| {
+        let is_dir = entry
+            .file_type()
+            .map(|file_type| file_type.is_dir())
+            .unwrap_or(false);
+        let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+        let path = entry.path();
+        is_dir && !is_hidden && (path.join(".git").exists() || holds_clones(&path))
+    })
 }
 
-fn error_out(msg: &str) -> ! {
-    unsafe {
-        if GLOBAL_ROLLBACK.is_some() {
-            rollback();
+/// Makes sure that the existing `dir` is a clone of `repo`, so that cloning
+/// it again can be skipped. A clone whose `origin` is another repo (like a
+/// fork) is accepted with a warning when one of its other remotes is `repo`,
+/// and gets `repo` as the remote `add_remote` when given.
+fn check_existing_clone(
+    conf: &Config,
+    repo: &Repo,
+    dir: &Path,
+    options: &CloneOptions,
+) -> Result<()> {
+    if !dir.join(".git").exists() {
+        return Err(Error::Occupied(format!(
+            "Unable to clone into {}: it already exists and is not a git repository",
+            dir.display()
+        )));
+    }
+
+    let remotes = gclone::get_remotes(dir)?;
+    let repo_id = repo.get_id(conf);
+    let is_repo = |url: &str| {
+        Repo::parse(url, conf)
+            .map(|remote| remote.get_id(conf) == repo_id)
+            .unwrap_or(false)
+    };
+    let origin = remotes.iter().find(|(name, _)| name == "origin");
+    if let Some((_, origin_url)) = origin {
+        if is_repo(origin_url) {
+            return Ok(());
         }
     }
-    eprintln!("{}", msg);
-    unsafe {
-        if STARTING_DIR.is_some() {
-            println!("{}", STARTING_DIR.as_ref().unwrap().display());
+    if let Some((name, _)) = remotes.iter().find(|(_, url)| is_repo(url)) {
+        if let Some((_, origin_url)) = origin {
+            eprintln!(
+                "warning: {} is a clone of {}, {} is its remote {}",
+                dir.display(),
+                origin_url,
+                options.url,
+                name
+            );
         }
+        return Ok(());
+    }
+
+    if let Some(name) = &options.add_remote {
+        git::add_remote(dir, name, &repo.get_clone_url())?;
+        eprintln!(
+            "added {} as the remote {} of {}",
+            repo.get_clone_url(),
+            name,
+            dir.display()
+        );
+        return Ok(());
     }
-    std::process::exit(1);
+    let remote_list: Vec<String> = remotes
+        .iter()
+        .map(|(name, url)| format!("\n  {}: {}", name, url))
+        .collect();
+    Err(Error::RemoteMismatch(format!(
+        "Unable to clone into {}: it is a git repository without a remote for {}{}\n\
+         Run `gclone --add-remote NAME {}` to add it as the remote NAME",
+        dir.display(),
+        repo.get_clone_url(),
+        remote_list.concat(),
+        options.url
+    )))
 }
 
-unsafe fn rollback() {
-    for path in GLOBAL_ROLLBACK.as_ref().unwrap() {
-        let _ = std::fs::remove_dir_all(path);
+fn get_full_path(conf: &Config, repo: &Repo, workspace: Option<&str>) -> Result<PathBuf> {
+    let base_path = get_base_path(conf, repo, workspace)?;
+    Ok(repo.get_fs_path_in(conf, base_path))
+}
+
+/// Returns the root of the workspace that `repo` is cloned into.
+fn get_base_path<'a>(conf: &'a Config, repo: &Repo, workspace: Option<&str>) -> Result<&'a Path> {
+    match workspace {
+        Some(workspace) => match conf.get_workspace_base_path(workspace) {
+            Some(base_path) => Ok(base_path),
+            None => Err(Error::Usage(format!("Unknown workspace: {}", workspace))),
+        },
+        None => Ok(conf.get_base_path_for(&repo.get_domain(), &repo.get_sub_path())),
     }
 }
 
-struct Options {
-    nocd: bool,
-    local: bool,
+/// Clones into a hidden sibling of `clone_dir` and only moves the repo into
+/// place once git succeeded, so that a failed or interrupted clone never
+/// leaves a half-populated repo behind.
+fn clone_atomically(
+    transaction: &mut Transaction,
     url: String,
+    fallback_url: Option<String>,
+    clone_dir: &Path,
+    git_args: &[String],
+) -> Result<()> {
+    let file_name = clone_dir
+        .file_name()
+        .ok_or_else(|| Error::Parse("root repo cannot be used".to_string()))?;
+    let mut temp_prefix = OsString::from(".");
+    temp_prefix.push(file_name);
+    temp_prefix.push(".gclone-");
+    remove_stale_temp_dirs(clone_dir, &temp_prefix.to_string_lossy());
+    let mut temp_name = temp_prefix;
+    temp_name.push(process::id().to_string());
+    let temp_dir = clone_dir.with_file_name(temp_name);
+
+    transaction.record(&temp_dir);
+    let first_url = url.clone();
+    let cloned_url = with_fallback(url, fallback_url, |url| {
+        // A failed attempt may leave the temporary directory behind.
+        if temp_dir.exists() {
+            let _ = fs::remove_dir_all(&temp_dir);
+        }
+        eprintln!("using url: {}", url);
+        git::clone(transaction.get_interrupt_guard(), url, &temp_dir, git_args)
+    })?;
+    if cloned_url != first_url {
+        eprintln!("cloned using url: {}", cloned_url);
+    }
+    fs::rename(&temp_dir, clone_dir).map_err(|err| {
+        Error::Fs(
+            format!("Unable to move the clone to {}", clone_dir.display()),
+            err,
+        )
+    })
 }
 
-fn parse_args() -> Options {
-    let args: Vec<String> = env::args().collect();
-    let mut options = Options {
-        nocd: false,
-        local: false,
-        url: String::new(),
+/// Removes the temporary siblings of `clone_dir` (named `temp_prefix`
+/// followed by a pid) left behind by gclones that were killed before they
+/// could clean up. Those of gclones that are still running are kept.
+fn remove_stale_temp_dirs(clone_dir: &Path, temp_prefix: &str) {
+    let parent_dir = match clone_dir.parent() {
+        Some(parent_dir) => parent_dir,
+        None => return,
+    };
+    let entries = match fs::read_dir(parent_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
     };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let pid = match file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(temp_prefix))
+        {
+            // kill takes negative pids as process groups.
+            Some(pid) if pid.parse::<i32>().is_ok_and(|pid| pid > 0) => pid.to_string(),
+            _ => continue,
+        };
+        let is_running = process::Command::new("kill")
+            .args(["-0", &pid])
+            .stderr(process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true);
+        if !is_running {
+            eprintln!(
+                "removing {} left behind by an earlier clone",
+                entry.path().display()
+            );
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
 
-    let mut index = 1;
-    for arg in &args[1..] {
-        if &arg[0..2] == "--" {
-            match arg.as_ref() {
-                "--local" => {
-                    options.local = true;
-                }
-                "--nocd" => {
-                    options.nocd = true;
-                }
-                "--get-base-dir" => {
-                    let conf = config::get_config();
-                    println!("{}", conf.get_base_path().display());
-                    std::process::exit(0);
-                }
-                "--get-base-domain" => {
-                    let conf = config::get_config();
-                    println!("{}", conf.get_domain());
-                    std::process::exit(0);
-                }
-                "--match-prefix" => {
-                    if index + 1 >= args.len() {
-                        print_matches("");
-                    } else {
-                        print_matches(&args[index + 1]);
-                    }
-                    std::process::exit(0);
-                }
-                _ => {
-                    error_out(&format!("Unknown arg: {}", arg));
-                }
-            }
-        } else {
-            options.url = arg.clone();
+/// Runs `attempt` against `url`, and against `fallback_url` when the first
+/// attempt failed in a way the other protocol might not. Returns the url that
+/// worked.
+fn with_fallback<F>(url: String, fallback_url: Option<String>, mut attempt: F) -> Result<String>
+where
+    F: FnMut(&str) -> Result<std::result::Result<(), git::Failure>>,
+{
+    let failure = match attempt(&url)? {
+        Ok(()) => return Ok(url),
+        Err(failure) => failure,
+    };
+    match fallback_url {
+        Some(fallback_url) if failure.is_protocol_specific() => {
+            eprintln!("{}, retrying with {}", failure, fallback_url);
+            attempt(&fallback_url)?.map_err(git::Failure::into_error)?;
+            Ok(fallback_url)
         }
-        index += 1;
+        _ => Err(failure.into_error()),
     }
+}
 
-    if options.url.is_empty() {
-        error_out("Please provide a URL!");
+/// Reports `err` and exits. Older wrappers cd into whatever is printed on
+/// stdout, so they are handed the starting directory to stay where they are.
+fn error_out(err: &Error, starting_dir: Option<&Path>) -> ! {
+    eprintln!("{}", err);
+    let _ = write_cd_target(None, starting_dir);
+    std::process::exit(get_exit_code(err));
+}
+
+fn get_exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Usage(_) => 2,
+        Error::Parse(_) => 3,
+        Error::Config(_) => 4,
+        Error::Fs(_, _) => 5,
+        Error::Git(_) => 6,
+        Error::Occupied(_) => 7,
+        Error::RemoteMismatch(_) => 8,
+        Error::Signal(_) => 9,
+        Error::Interrupted => 130,
     }
-    options
 }
 
-fn print_matches(input: &str) {
-    let config = config::get_config();
-    let hints = completion::get_matches(&config, input);
+fn print_matches(input: &str) -> Result<()> {
+    let config = gclone::get_config()?;
+    let hints = gclone::get_matches(&config, input);
     for (hint, description) in hints {
         println!("{}\t{}", hint, description);
     }
+    Ok(())
+}
+
+fn print_which(path: &Path) -> Result<()> {
+    let conf = gclone::get_config()?;
+    let repo = Repo::from_fs_path(path, &conf)?;
+    println!("shorthand: {}", repo.get_shorthand(&conf));
+    match (repo.get_ssh_url(&conf), repo.get_https_url(&conf)) {
+        (Some(ssh_url), Some(https_url)) => {
+            println!("ssh: {}", ssh_url);
+            println!("https: {}", https_url);
+        }
+        _ => println!("url: {}", repo.get_clone_url()),
+    }
+    Ok(())
+}
+
+fn print_config(key: Option<&str>) -> Result<()> {
+    let conf = gclone::get_config()?;
+    let settings = [
+        ("basePath", conf.get_base_path().display().to_string()),
+        ("defaultDomain", conf.get_domain().to_string()),
+        ("localDomain", conf.get_local_domain().to_string()),
+    ];
+    match key {
+        Some(key) => match settings.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) => println!("{}", value),
+            None => return Err(Error::Usage(format!("Unknown config key: {}", key))),
+        },
+        None => {
+            for (name, value) in &settings {
+                println!("{} = {}", name, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_and_exit(output: &dyn std::fmt::Display) -> ! {
+    println!("{}", output);
+    std::process::exit(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_existing_dir, clone_atomically};
+    use crate::cli::CloneOptions;
+    use crate::test_util::get_test_dir;
+    use crate::transaction::Transaction;
+    use gclone::{Config, Error, Repo, Result};
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn get_clone_options(url: &str) -> CloneOptions {
+        CloneOptions {
+            nocd: false,
+            local: false,
+            workspace: None,
+            add_remote: None,
+            url: url.to_string(),
+            git_args: Vec::new(),
+        }
+    }
+
+    /// Checks the existing directory of `options.url` in the tree rooted at
+    /// `base_path`.
+    fn check_existing(base_path: &Path, options: &CloneOptions) -> Result<bool> {
+        let conf = Config::default();
+        let repo = Repo::parse(&options.url, &conf)?;
+        let dir = repo.get_fs_path_in(&conf, base_path);
+        check_existing_dir(&conf, &repo, &dir, base_path, options)
+    }
+
+    fn git_init(dir: &Path) {
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .arg(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    fn add_remote(dir: &Path, name: &str, url: &str) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["remote", "add", name, url])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_existing_clone_with_matching_remote() {
+        let test_dir = get_test_dir("main-matching-remote");
+        let clone_dir = test_dir.join("github.com/allonsy/gclone");
+        git_init(&clone_dir);
+        add_remote(&clone_dir, "origin", "git@github.com:allonsy/gclone.git");
+        assert!(check_existing(&test_dir, &get_clone_options("allonsy/gclone")).unwrap());
+
+        let fork_dir = test_dir.join("github.com/allonsy/fork");
+        git_init(&fork_dir);
+        add_remote(&fork_dir, "origin", "https://github.com/someone/fork");
+        add_remote(&fork_dir, "upstream", "https://github.com/allonsy/fork");
+        assert!(check_existing(&test_dir, &get_clone_options("allonsy/fork")).unwrap());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_existing_clone_with_mismatching_remote() {
+        let test_dir = get_test_dir("main-mismatching-remote");
+        let clone_dir = test_dir.join("github.com/allonsy/gclone");
+        git_init(&clone_dir);
+        add_remote(&clone_dir, "origin", "https://github.com/someone/other");
+
+        let result = check_existing(&test_dir, &get_clone_options("allonsy/gclone"));
+        assert!(matches!(result, Err(Error::RemoteMismatch(_))));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_existing_clone_add_remote() {
+        let test_dir = get_test_dir("main-add-remote");
+        let clone_dir = test_dir.join("github.com/allonsy/gclone");
+        git_init(&clone_dir);
+        add_remote(&clone_dir, "origin", "https://github.com/someone/other");
+
+        let mut options = get_clone_options("allonsy/gclone");
+        options.add_remote = Some("upstream".to_string());
+        assert!(check_existing(&test_dir, &options).unwrap());
+        let remotes = gclone::get_remotes(&clone_dir).unwrap();
+        let upstream = remotes.iter().find(|(name, _)| name == "upstream").unwrap();
+        let conf = Config::default();
+        let upstream_repo = Repo::parse(&upstream.1, &conf).unwrap();
+        let repo = Repo::parse("allonsy/gclone", &conf).unwrap();
+        assert_eq!(upstream_repo.get_id(&conf), repo.get_id(&conf));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_navigate_to_tree_directories() {
+        let test_dir = get_test_dir("main-navigate");
+        let clone_dir = test_dir.join("github.com/allonsy/gclone");
+        git_init(&clone_dir);
+        fs::create_dir(clone_dir.join("src")).unwrap();
+
+        for url in ["allonsy", "allonsy/", "allonsy/gclone/src"] {
+            assert!(!check_existing(&test_dir, &get_clone_options(url)).unwrap());
+        }
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_clone_root_must_be_a_clone() {
+        let test_dir = get_test_dir("main-occupied");
+        fs::create_dir_all(test_dir.join("github.com/allonsy/gclone")).unwrap();
+
+        let result = check_existing(&test_dir, &get_clone_options("allonsy/gclone"));
+        assert!(matches!(result, Err(Error::Occupied(_))));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_clone_atomically() {
+        let test_dir = get_test_dir("main-clone");
+        let source = test_dir.join("source.git");
+        let status = Command::new("git")
+            .args(["init", "-q", "--bare"])
+            .arg(&source)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let clone_dir = test_dir.join("tree/repo");
+        let mut transaction = Transaction::new().unwrap();
+        transaction.create_dir_all(&test_dir.join("tree")).unwrap();
+        // Left behind by a killed gclone, whose pid can't be running.
+        fs::create_dir(test_dir.join("tree/.repo.gclone-2147483647")).unwrap();
+        // Kept, as the test runner is still running.
+        let running_temp_dir = test_dir.join(format!(
+            "tree/.repo.gclone-{}",
+            std::os::unix::process::parent_id()
+        ));
+        fs::create_dir(&running_temp_dir).unwrap();
+        let url = source.display().to_string();
+        clone_atomically(&mut transaction, url, None, &clone_dir, &["-q".to_string()]).unwrap();
+        transaction.commit();
+
+        assert!(clone_dir.join(".git").is_dir());
+        assert!(running_temp_dir.is_dir());
+        let entries: Vec<_> = fs::read_dir(test_dir.join("tree")).unwrap().collect();
+        assert_eq!(entries.len(), 2);
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_clone_leaves_nothing_behind() {
+        let test_dir = get_test_dir("main-failed-clone");
+        let clone_dir = test_dir.join("tree/repo");
+        {
+            let mut transaction = Transaction::new().unwrap();
+            transaction.create_dir_all(&test_dir.join("tree")).unwrap();
+            let url = test_dir.join("missing.git").display().to_string();
+            let result = clone_atomically(&mut transaction, url, None, &clone_dir, &[]);
+            assert!(result.is_err());
+        }
+
+        assert!(!clone_dir.exists());
+        assert!(!test_dir
+            .join(format!("tree/.repo.gclone-{}", std::process::id()))
+            .exists());
+        assert!(!test_dir.join("tree").exists());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
 }
@@ -0,0 +1,82 @@
+use crate::error::{Error, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Returns the names and urls of the remotes of the clone at `dir`, as git
+/// reads them from its config (includes and quoted names too).
+pub fn get_remotes(dir: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(dir.join(".git"))
+        .args(["config", "--null", "--get-regexp", r"^remote\..*\.url$"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|err| Error::Git(format!("Failed to spawn git config process: {}", err)))?;
+    // git config exits with 1 when nothing matches.
+    if !output.status.success() && output.status.code() != Some(1) {
+        return Err(Error::Git(format!(
+            "Unable to read the remotes of {}",
+            dir.display()
+        )));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let remotes = stdout
+        .split('\0')
+        .filter_map(|entry| {
+            // Remote names may contain spaces, but not newlines.
+            let (key, url) = entry.split_once('\n')?;
+            let name = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some((name.to_string(), url.to_string()))
+        })
+        .collect();
+    Ok(remotes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_remotes;
+    use crate::test_util::get_test_dir;
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_remotes_as_git_reads_them() {
+        let test_dir = get_test_dir("remotes");
+        let clone_dir = test_dir.join("clone");
+        let status = Command::new("git")
+            .args(["init", "-q"])
+            .arg(&clone_dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let config_path = clone_dir.join(".git/config");
+        let config = fs::read_to_string(&config_path).unwrap()
+            + "[remote \"my \\\"fork\\\"\"]\n\turl = https://gitlab.com/me/repo\n\
+               [include]\n\tpath = extra\n";
+        fs::write(&config_path, config).unwrap();
+        fs::write(
+            clone_dir.join(".git/extra"),
+            "[remote \"origin\"]\n\turl = git@github.com:allonsy/gclone.git\n",
+        )
+        .unwrap();
+
+        let remotes = get_remotes(&clone_dir).unwrap();
+        assert_eq!(
+            remotes,
+            vec![
+                (
+                    "my \"fork\"".to_string(),
+                    "https://gitlab.com/me/repo".to_string()
+                ),
+                (
+                    "origin".to_string(),
+                    "git@github.com:allonsy/gclone.git".to_string()
+                ),
+            ]
+        );
+        assert!(get_remotes(&test_dir).is_ok_and(|remotes| remotes.is_empty()));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
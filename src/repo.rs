@@ -1,166 +1,528 @@
 use crate::config;
-use crate::error_out;
-use std::path::PathBuf;
-use url;
+use crate::error::{Error, Result};
+use crate::remote;
+use crate::repo_id::RepoId;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+static DEFAULT_SSH_USER: &str = "git";
 
 #[derive(Eq, PartialEq, Debug)]
 struct ShortHandUrl {
     is_https: bool,
+    user: Option<String>,
     domain: String,
+    ssh_port: Option<u16>,
+    https_port: Option<u16>,
     path: String,
 }
 
+#[derive(Eq, PartialEq, Debug)]
+struct LocalPath {
+    is_file_url: bool,
+    path: PathBuf,
+    domain: String,
+    sub_path: String,
+}
+
 #[derive(Eq, PartialEq, Debug)]
 enum RepoUrl {
     Https(url::Url),
-    SSH(url::Url),
+    Http(url::Url),
+    Ssh(url::Url),
+    Git(url::Url),
+    Local(LocalPath),
     Short(ShortHandUrl),
 }
 
+/// A repo given as a url, a shorthand like `owner/repo` or a local path.
 #[derive(Eq, PartialEq, Debug)]
 pub struct Repo {
     url: RepoUrl,
 }
 
 impl Repo {
-    pub fn parse(url: &str) -> Repo {
-        if is_https_url(url) {
-            let parsed_url = url::Url::parse(&url);
-            if parsed_url.is_ok() {
-                Repo {
-                    url: RepoUrl::Https(parsed_url.unwrap()),
-                }
-            } else {
-                error_out(&format!("Unknown https url: {}", url));
-            }
+    pub fn parse(url: &str, conf: &config::Config) -> Result<Repo> {
+        let repo_url = if is_https_url(url) {
+            RepoUrl::Https(parse_url(url, "https")?)
+        } else if is_http_url(url) {
+            RepoUrl::Http(parse_url(url, "http")?)
         } else if is_ssh_url(url) {
-            let parsed_url = url::Url::parse(&url);
-            if parsed_url.is_ok() {
-                Repo {
-                    url: RepoUrl::SSH(parsed_url.unwrap()),
-                }
-            } else {
-                error_out(&format!("Unknown ssh url: {}", url));
-            }
+            RepoUrl::Ssh(parse_url(url, "ssh")?)
+        } else if is_git_url(url) {
+            RepoUrl::Git(parse_url(url, "git")?)
+        } else if is_file_url(url) {
+            let local_path = parse_url(url, "file")?
+                .to_file_path()
+                .map_err(|_| Error::Parse(format!("Unknown file url: {}", url)))?;
+            RepoUrl::Local(LocalPath::new(local_path, true, conf)?)
+        } else if is_local_path(url) {
+            RepoUrl::Local(LocalPath::new(PathBuf::from(url), false, conf)?)
         } else {
             let paths: Vec<&str> = url.split('/').collect();
             if paths[0].contains(':') {
-                let is_https = false;
                 let domain_paths = paths[0].split(':').collect::<Vec<&str>>()[0]
                     .split('@')
                     .collect::<Vec<&str>>();
-                let domain = if domain_paths.len() == 1 {
-                    domain_paths[0]
+                let (user, domain) = if domain_paths.len() == 1 {
+                    (None, domain_paths[0])
                 } else {
-                    domain_paths[1]
+                    (Some(domain_paths[0].to_string()), domain_paths[1])
                 };
                 let mut actual_path = paths[0].split(':').collect::<Vec<&str>>()[1].to_string();
                 for path in &paths[1..] {
-                    actual_path = format!("{}/{}", actual_path, path.to_string());
+                    actual_path = format!("{}/{}", actual_path, path);
                 }
 
-                Repo {
-                    url: RepoUrl::Short(ShortHandUrl {
-                        is_https,
-                        domain: domain.to_string(),
-                        path: actual_path,
-                    }),
+                if let Some(local_path) = LocalPath::from_tree(domain, &actual_path, conf)? {
+                    return Ok(Repo {
+                        url: RepoUrl::Local(local_path),
+                    });
                 }
+
+                let is_https = user.is_none() && conf.get_domain_is_https(domain).unwrap_or(false);
+                RepoUrl::Short(ShortHandUrl {
+                    is_https,
+                    user: user.or_else(|| conf.get_ssh_user(domain).cloned()),
+                    domain: domain.to_string(),
+                    ssh_port: conf.get_ssh_port(domain),
+                    https_port: conf.get_https_port(domain),
+                    path: actual_path,
+                })
             } else {
-                let conf = config::get_config();
                 let (domain, path) = (conf.get_domain().clone(), url.to_string());
-                let shorthand = ShortHandUrl {
-                    is_https: conf.get_is_https(),
+                RepoUrl::Short(ShortHandUrl {
+                    is_https: conf.get_is_https_for(&domain),
+                    user: conf.get_ssh_user(&domain).cloned(),
+                    ssh_port: conf.get_ssh_port(&domain),
+                    https_port: conf.get_https_port(&domain),
                     domain,
                     path,
+                })
+            }
+        };
+
+        Ok(Repo { url: repo_url })
+    }
+
+    /// Returns the repo of the clone containing `path`: the repo whose tree
+    /// location is the clone, or else the `origin` remote of the clone.
+    ///
+    /// When a remote of a clone in the tree is the same repo, its url is used
+    /// to keep details that the tree location loses, like a `.git` suffix.
+    pub fn from_fs_path(path: &Path, conf: &config::Config) -> Result<Repo> {
+        let path = fs::canonicalize(path)
+            .map_err(|err| Error::Fs(format!("Unable to resolve {}", path.display()), err))?;
+        let repo_dir = path
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .ok_or_else(|| {
+                Error::Parse(format!("{} is not inside a git repository", path.display()))
+            })?;
+
+        let mut remotes = remote::get_remotes(repo_dir)?;
+        remotes.sort_by_key(|(name, _)| name != "origin");
+        let remotes: Vec<Repo> = remotes
+            .iter()
+            .filter_map(|(_, url)| Repo::parse(url, conf).ok())
+            .collect();
+        if let Some(repo) = Repo::from_tree_path(repo_dir, conf) {
+            let id = repo.get_id(conf);
+            return Ok(remotes
+                .into_iter()
+                .find(|remote| remote.get_id(conf) == id)
+                .unwrap_or(repo));
+        }
+        remotes.into_iter().next().ok_or_else(|| {
+            Error::Parse(format!(
+                "{} is outside of the tree and has no remote",
+                repo_dir.display()
+            ))
+        })
+    }
+
+    /// Maps a directory of the tree back to its repo, checking every layout
+    /// that could have placed a repo there.
+    fn from_tree_path(dir: &Path, conf: &config::Config) -> Option<Repo> {
+        for base_path in conf.get_all_base_paths() {
+            let base_path = fs::canonicalize(base_path).unwrap_or_else(|_| base_path.clone());
+            let rel_path = match dir.strip_prefix(&base_path) {
+                Ok(rel_path) => rel_path,
+                Err(_) => continue,
+            };
+            let rel_path: Vec<String> = rel_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+
+            for (domain, sub_path) in get_tree_candidates(conf, &rel_path) {
+                let shorthand = if domain == *conf.get_domain() {
+                    sub_path
+                } else {
+                    format!("{}:{}", domain, sub_path)
                 };
-                Repo {
-                    url: RepoUrl::Short(shorthand),
+                if let Ok(repo) = Repo::parse(&shorthand, conf) {
+                    if repo.get_fs_path_in(conf, &base_path) == dir {
+                        return Some(repo);
+                    }
                 }
             }
         }
+        None
+    }
+
+    /// Returns the shortest name `Repo::parse` understands as this repo, like
+    /// `allonsy/gclone` for repos of the default domain or
+    /// `gitlab.com:group/repo` for others.
+    pub fn get_shorthand(&self, conf: &config::Config) -> String {
+        let domain = self.get_domain();
+        let sub_path = self.get_sub_path();
+        let sub_path = sub_path.trim_matches('/');
+        let sub_path = sub_path.strip_suffix(".git").unwrap_or(sub_path);
+        if domain == *conf.get_domain() {
+            sub_path.to_string()
+        } else {
+            format!("{}:{}", domain, sub_path)
+        }
     }
 
     pub fn get_clone_url(&self) -> String {
         match &self.url {
-            RepoUrl::Https(hurl) => hurl.as_str().to_string(),
-            RepoUrl::SSH(surl) => surl.as_str().to_string(),
-            RepoUrl::Short(short) => {
-                let url_start = if short.is_https { "https://" } else { "git@" };
-                let div_char = if short.is_https { '/' } else { ':' };
-                format!("{}{}{}{}", url_start, short.domain, div_char, short.path)
+            RepoUrl::Https(url) | RepoUrl::Http(url) | RepoUrl::Ssh(url) | RepoUrl::Git(url) => {
+                url.as_str().to_string()
+            }
+            RepoUrl::Local(local) => {
+                if local.is_file_url {
+                    format!("file://{}", local.path.display())
+                } else {
+                    format!("{}", local.path.display())
+                }
             }
+            RepoUrl::Short(short) if short.is_https => short.get_https_url(),
+            RepoUrl::Short(short) => short.get_ssh_url(),
         }
     }
 
-    pub fn get_fs_path(&self) -> PathBuf {
-        let conf = config::get_config();
-        let mut path = conf.get_base_path().clone();
-        path.push(self.get_domain());
-        for p in self.get_sub_path().split('/') {
-            path.push(p);
+    /// Returns the url to clone the repo over ssh, or `None` for local repos.
+    /// The user and port come from the domain settings, like for shorthands.
+    pub fn get_ssh_url(&self, conf: &config::Config) -> Option<String> {
+        match &self.url {
+            RepoUrl::Ssh(url) => Some(url.as_str().to_string()),
+            RepoUrl::Short(short) => Some(short.get_ssh_url()),
+            RepoUrl::Https(url) | RepoUrl::Http(url) | RepoUrl::Git(url) => {
+                Some(ShortHandUrl::from_url(url, conf)?.get_ssh_url())
+            }
+            RepoUrl::Local(_) => None,
         }
+    }
 
-        let extension = path.extension();
-        if extension.is_some() && extension.unwrap() == "git" {
-            let last_child = path.file_stem().unwrap();
-            let mut new_path: PathBuf = PathBuf::from(path.parent().unwrap());
-            new_path.push(last_child);
-            return new_path;
+    /// Returns the url to clone the repo over https, or `None` for local repos.
+    /// The port comes from the domain settings, like for shorthands.
+    pub fn get_https_url(&self, conf: &config::Config) -> Option<String> {
+        match &self.url {
+            RepoUrl::Https(url) => Some(url.as_str().to_string()),
+            RepoUrl::Short(short) => Some(short.get_https_url()),
+            RepoUrl::Http(url) | RepoUrl::Ssh(url) | RepoUrl::Git(url) => {
+                Some(ShortHandUrl::from_url(url, conf)?.get_https_url())
+            }
+            RepoUrl::Local(_) => None,
         }
+    }
 
-        path
+    /// Returns the url of the other protocol when the clone url is an ssh or
+    /// https one: the https url for ssh and the ssh url for https.
+    pub fn get_fallback_url(&self, conf: &config::Config) -> Option<String> {
+        match &self.url {
+            RepoUrl::Ssh(_) => self.get_https_url(conf),
+            RepoUrl::Https(_) => self.get_ssh_url(conf),
+            RepoUrl::Short(short) if short.is_https => self.get_ssh_url(conf),
+            RepoUrl::Short(_) => self.get_https_url(conf),
+            RepoUrl::Http(_) | RepoUrl::Git(_) | RepoUrl::Local(_) => None,
+        }
+    }
+
+    /// Returns the protocol independent identity of the repo. Ports are only
+    /// kept when they are neither the default of the protocol nor the one
+    /// configured for the domain.
+    pub fn get_id(&self, conf: &config::Config) -> RepoId {
+        match &self.url {
+            RepoUrl::Https(url) | RepoUrl::Http(url) | RepoUrl::Ssh(url) | RepoUrl::Git(url) => {
+                let domain = url.host_str().unwrap_or_default();
+                let usual_ports = match &self.url {
+                    RepoUrl::Https(_) => [Some(443), conf.get_https_port(domain)],
+                    RepoUrl::Http(_) => [Some(80), None],
+                    RepoUrl::Ssh(_) => [Some(22), conf.get_ssh_port(domain)],
+                    _ => [Some(9418), None],
+                };
+                let port = url
+                    .port()
+                    .filter(|port| !usual_ports.contains(&Some(*port)));
+                RepoId::new(domain, port, url.path())
+            }
+            RepoUrl::Local(local) => RepoId::new(&local.domain, None, &local.sub_path),
+            // The ports of shorthands always come from the domain settings.
+            RepoUrl::Short(short) => RepoId::new(&short.domain, None, &short.path),
+        }
+    }
+
+    pub fn get_fs_path(&self, conf: &config::Config) -> PathBuf {
+        let base_path = conf.get_base_path_for(&self.get_domain(), &self.get_sub_path());
+        self.get_fs_path_in(conf, base_path)
+    }
+
+    /// Returns the path of the repo in the workspace rooted at `base_path`,
+    /// ignoring the workspace rules.
+    pub fn get_fs_path_in(&self, conf: &config::Config, base_path: &Path) -> PathBuf {
+        let domain = self.get_domain();
+        let layout = conf.get_layout(&domain);
+        base_path.join(layout.render(&domain, &self.get_sub_path()))
     }
 
     pub fn get_repo_name(&self) -> String {
-        let full_path = self.get_fs_path();
-        let file_name = full_path
-            .file_name()
-            .unwrap_or_else(|| full_path.as_os_str())
-            .to_str()
-            .unwrap();
-        file_name.to_string()
+        let sub_path = self.get_sub_path();
+        let repo_name = sub_path
+            .split('/')
+            .rfind(|component| !component.is_empty())
+            .unwrap_or_default();
+        repo_name
+            .strip_suffix(".git")
+            .unwrap_or(repo_name)
+            .to_string()
     }
 
     pub fn get_domain(&self) -> String {
         match &self.url {
-            RepoUrl::Https(hurl) => hurl.domain().unwrap().to_string(),
-            RepoUrl::SSH(surl) => surl.domain().unwrap().to_string(),
+            RepoUrl::Https(url) | RepoUrl::Http(url) | RepoUrl::Ssh(url) | RepoUrl::Git(url) => {
+                url.host_str().unwrap_or_default().to_string()
+            }
+            RepoUrl::Local(local) => local.domain.clone(),
             RepoUrl::Short(short) => short.domain.clone(),
         }
     }
 
     pub fn get_sub_path(&self) -> String {
         match &self.url {
-            RepoUrl::Https(hurl) => remove_leading_slash(hurl.path()),
-            RepoUrl::SSH(surl) => remove_leading_slash(surl.path()),
+            RepoUrl::Https(url) | RepoUrl::Http(url) | RepoUrl::Ssh(url) | RepoUrl::Git(url) => {
+                remove_leading_slash(url.path())
+            }
+            RepoUrl::Local(local) => local.sub_path.clone(),
             RepoUrl::Short(short) => short.path.clone(),
         }
     }
 }
 
-fn remove_leading_slash(path: &str) -> String {
-    if &path[0..1] == "/" {
-        path[1..].to_string()
+impl ShortHandUrl {
+    /// Returns the shorthand of the repo at `url`, with the domain settings
+    /// that `Repo::parse` would use for it.
+    fn from_url(url: &url::Url, conf: &config::Config) -> Option<ShortHandUrl> {
+        let domain = url.host_str()?;
+        Some(ShortHandUrl {
+            is_https: url.scheme() == "https",
+            user: conf.get_ssh_user(domain).cloned(),
+            domain: domain.to_string(),
+            ssh_port: conf.get_ssh_port(domain),
+            https_port: conf.get_https_port(domain),
+            path: remove_leading_slash(url.path()),
+        })
+    }
+
+    fn get_ssh_url(&self) -> String {
+        let user = self.user.as_deref().unwrap_or(DEFAULT_SSH_USER);
+        match self.ssh_port {
+            Some(port) => format!("ssh://{}@{}:{}/{}", user, self.domain, port, self.path),
+            None => format!("{}@{}:{}", user, self.domain, self.path),
+        }
+    }
+
+    fn get_https_url(&self) -> String {
+        match self.https_port {
+            Some(port) => format!("https://{}:{}/{}", self.domain, port, self.path),
+            None => format!("https://{}/{}", self.domain, self.path),
+        }
+    }
+}
+
+impl LocalPath {
+    fn new(path: PathBuf, is_file_url: bool, conf: &config::Config) -> Result<LocalPath> {
+        let path = if path.is_absolute() {
+            normalize_path(&path)
+        } else {
+            let cwd = env::current_dir().map_err(|err| {
+                Error::Fs(
+                    "Unable to retrieve current working directory".to_string(),
+                    err,
+                )
+            })?;
+            normalize_path(&cwd.join(path))
+        };
+
+        let mut best_match: Option<(&PathBuf, &String)> = None;
+        for (local_root, domain) in conf.get_local_paths() {
+            let is_longer = best_match.is_none_or(|(best, _)| {
+                local_root.components().count() > best.components().count()
+            });
+            if path.starts_with(local_root) && path != *local_root && is_longer {
+                best_match = Some((local_root, domain));
+            }
+        }
+
+        let (domain, sub_path) = match best_match {
+            Some((local_root, domain)) => (domain.clone(), path.strip_prefix(local_root).unwrap()),
+            None => (
+                conf.get_local_domain().clone(),
+                path.strip_prefix("/").unwrap_or(&path),
+            ),
+        };
+        let sub_path = sub_path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+
+        Ok(LocalPath {
+            is_file_url,
+            path,
+            domain,
+            sub_path,
+        })
+    }
+
+    fn from_tree(domain: &str, sub_path: &str, conf: &config::Config) -> Result<Option<LocalPath>> {
+        let sub_path = sub_path.trim_end_matches('/');
+        if domain == conf.get_local_domain() {
+            let path = get_local_source(Path::new("/").join(sub_path));
+            return LocalPath::new(path, false, conf).map(Some);
+        }
+
+        for (local_root, local_domain) in conf.get_local_paths() {
+            if domain == local_domain {
+                let path = get_local_source(local_root.join(sub_path));
+                return LocalPath::new(path, false, conf).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The tree drops the `.git` suffix of bare repos, so `path` may name a bare
+/// repo without its suffix. Returns the path of the repo as it is on disk.
+fn get_local_source(path: PathBuf) -> PathBuf {
+    let mut bare_path = path.clone().into_os_string();
+    bare_path.push(".git");
+    let bare_path = PathBuf::from(bare_path);
+    if !path.exists() && bare_path.exists() {
+        bare_path
     } else {
-        path.to_string()
+        path
+    }
+}
+
+/// Returns the `(domain, sub_path)` pairs the layouts map the directory at
+/// `rel_path` (relative to a base path) to.
+fn get_tree_candidates(conf: &config::Config, rel_path: &[String]) -> Vec<(String, String)> {
+    let mut candidates = Vec::new();
+    let mut add_candidate = |domain: &str, root: &Path| {
+        let root: Vec<String> = root
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        if rel_path.len() > root.len() && rel_path[..root.len()] == root[..] {
+            let tail = rel_path[root.len()..].join("/");
+            let sub_path = conf.get_layout(domain).get_sub_path(domain, &tail);
+            candidates.push((domain.to_string(), sub_path));
+        }
+    };
+
+    for domain in conf.get_layout_domains() {
+        add_candidate(domain, &conf.get_layout(domain).get_root(domain));
+    }
+    let default_layout = conf.get_default_layout();
+    if default_layout.get_root_glob().is_none() {
+        let domain = conf.get_domain();
+        add_candidate(domain, &default_layout.get_root(domain));
+    }
+    for count in 1..rel_path.len() {
+        if let Some(domain) = default_layout.get_root_domain(&rel_path[..count].join("/")) {
+            add_candidate(&domain, &default_layout.get_root(&domain));
+        }
+    }
+    candidates
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component.as_os_str()),
+        }
     }
+    normalized
+}
+
+fn remove_leading_slash(path: &str) -> String {
+    path.strip_prefix('/').unwrap_or(path).to_string()
+}
+
+fn parse_url(url: &str, scheme_name: &str) -> Result<url::Url> {
+    url::Url::parse(url).map_err(|_| Error::Parse(format!("Unknown {} url: {}", scheme_name, url)))
 }
 
 fn is_https_url(url: &str) -> bool {
-    url.contains("https://")
+    url.starts_with("https://")
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://")
 }
 
 fn is_ssh_url(url: &str) -> bool {
-    url.contains("ssh://")
+    url.starts_with("ssh://") || url.starts_with("git+ssh://") || url.starts_with("ssh+git://")
+}
+
+fn is_git_url(url: &str) -> bool {
+    url.starts_with("git://")
+}
+
+fn is_file_url(url: &str) -> bool {
+    url.starts_with("file://")
+}
+
+fn is_local_path(url: &str) -> bool {
+    url.starts_with('/') || url.starts_with("./") || url.starts_with("../")
 }
 
 #[cfg(test)]
 mod tests {
+    use super::LocalPath;
     use super::Repo;
     use super::RepoUrl;
     use super::ShortHandUrl;
     use crate::config;
+    use crate::error::Error;
+    use crate::layout::Layout;
+    use crate::test_util;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn parse(url: &str) -> Repo {
+        Repo::parse(url, &config::Config::default()).unwrap()
+    }
+
+    fn get_local_config() -> config::Config {
+        let mut conf = config::Config::default();
+        conf.add_local_path(PathBuf::from("/srv/mirrors"), "mirrors".to_string());
+        conf.add_local_path(
+            PathBuf::from("/srv/mirrors/vendor"),
+            "vendor-mirrors".to_string(),
+        );
+        conf
+    }
 
     #[test]
     fn test_https_url() {
@@ -168,27 +530,27 @@ mod tests {
         let expected_repo = Repo {
             url: RepoUrl::Https(url::Url::parse(url).unwrap()),
         };
-        assert_eq!(Repo::parse(url), expected_repo);
+        assert_eq!(parse(url), expected_repo);
     }
 
     #[test]
     fn test_https_url_domain() {
         let url = "https://github.com/user/repo.git";
-        assert_eq!(Repo::parse(url).get_domain(), "github.com".to_string());
+        assert_eq!(parse(url).get_domain(), "github.com".to_string());
     }
 
     #[test]
     fn test_https_url_path() {
         let url = "https://github.com/user/repo.git";
         let expected_path = "user/repo.git";
-        assert_eq!(Repo::parse(url).get_sub_path(), expected_path);
+        assert_eq!(parse(url).get_sub_path(), expected_path);
     }
 
     #[test]
     fn test_https_url_clone_url() {
         let url = "https://github.com/user/repo.git";
         assert_eq!(
-            Repo::parse(url).get_clone_url(),
+            parse(url).get_clone_url(),
             "https://github.com/user/repo.git"
         );
     }
@@ -197,22 +559,22 @@ mod tests {
     fn test_ssh_url() {
         let url = "ssh://aur@aur.archlinux.org/user/repo.git";
         let expected_repo = Repo {
-            url: RepoUrl::SSH(url::Url::parse(url).unwrap()),
+            url: RepoUrl::Ssh(url::Url::parse(url).unwrap()),
         };
-        assert_eq!(Repo::parse(url), expected_repo);
+        assert_eq!(parse(url), expected_repo);
     }
 
     #[test]
     fn test_ssh_url_domain() {
         let url = "ssh://aur@aur.archlinux.org/user/repo.git";
-        assert_eq!(Repo::parse(url).get_domain(), "aur.archlinux.org");
+        assert_eq!(parse(url).get_domain(), "aur.archlinux.org");
     }
 
     #[test]
     fn test_ssh_url_clone_url() {
         let url = "ssh://aur@aur.archlinux.org/user/repo.git";
         assert_eq!(
-            Repo::parse(url).get_clone_url(),
+            parse(url).get_clone_url(),
             "ssh://aur@aur.archlinux.org/user/repo.git"
         );
     }
@@ -221,7 +583,135 @@ mod tests {
     fn test_ssh_url_path() {
         let url = "ssh://aur@aur.archlinux.org/user/repo.git";
         let expected_path = "user/repo.git";
-        assert_eq!(Repo::parse(url).get_sub_path(), expected_path);
+        assert_eq!(parse(url).get_sub_path(), expected_path);
+    }
+
+    #[test]
+    fn test_ssh_url_port() {
+        let url = "ssh://git@git.example.org:2222/team/repo.git";
+        let repo = parse(url);
+        assert_eq!(repo.get_domain(), "git.example.org");
+        assert_eq!(repo.get_sub_path(), "team/repo.git");
+        assert_eq!(repo.get_clone_url(), url);
+    }
+
+    #[test]
+    fn test_git_ssh_url() {
+        let url = "git+ssh://git@example.org/team/repo.git";
+        let expected_repo = Repo {
+            url: RepoUrl::Ssh(url::Url::parse(url).unwrap()),
+        };
+        assert_eq!(parse(url), expected_repo);
+    }
+
+    #[test]
+    fn test_git_ssh_url_domain_and_path() {
+        let url = "git+ssh://git@example.org/team/repo.git";
+        let repo = parse(url);
+        assert_eq!(repo.get_domain(), "example.org");
+        assert_eq!(repo.get_sub_path(), "team/repo.git");
+        assert_eq!(repo.get_clone_url(), url);
+    }
+
+    #[test]
+    fn test_http_url() {
+        let url = "http://internal-mirror/team/repo.git";
+        let expected_repo = Repo {
+            url: RepoUrl::Http(url::Url::parse(url).unwrap()),
+        };
+        assert_eq!(parse(url), expected_repo);
+    }
+
+    #[test]
+    fn test_http_url_domain() {
+        let url = "http://internal-mirror/team/repo.git";
+        assert_eq!(parse(url).get_domain(), "internal-mirror");
+    }
+
+    #[test]
+    fn test_http_url_ip_domain() {
+        let url = "http://10.0.0.5:8080/team/repo.git";
+        assert_eq!(parse(url).get_domain(), "10.0.0.5");
+    }
+
+    #[test]
+    fn test_http_url_path() {
+        let url = "http://internal-mirror/team/repo.git";
+        assert_eq!(parse(url).get_sub_path(), "team/repo.git");
+    }
+
+    #[test]
+    fn test_http_url_clone_url() {
+        let url = "http://internal-mirror/team/repo.git";
+        assert_eq!(
+            parse(url).get_clone_url(),
+            "http://internal-mirror/team/repo.git"
+        );
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        let conf = config::Config::default();
+        assert!(matches!(
+            Repo::parse("https://", &conf),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            Repo::parse("ssh://[::1", &conf),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_http_url_fs_path() {
+        let url = "http://internal-mirror/team/repo.git";
+        let mut expected_path = config::Config::default().get_base_path().clone();
+        expected_path.push("internal-mirror");
+        expected_path.push("team");
+        expected_path.push("repo");
+        assert_eq!(
+            parse(url).get_fs_path(&config::Config::default()),
+            expected_path
+        );
+    }
+
+    #[test]
+    fn test_git_url() {
+        let url = "git://example.org/proj.git";
+        let expected_repo = Repo {
+            url: RepoUrl::Git(url::Url::parse(url).unwrap()),
+        };
+        assert_eq!(parse(url), expected_repo);
+    }
+
+    #[test]
+    fn test_git_url_domain() {
+        let url = "git://example.org/proj.git";
+        assert_eq!(parse(url).get_domain(), "example.org");
+    }
+
+    #[test]
+    fn test_git_url_path() {
+        let url = "git://example.org/proj.git";
+        assert_eq!(parse(url).get_sub_path(), "proj.git");
+    }
+
+    #[test]
+    fn test_git_url_clone_url() {
+        let url = "git://example.org/proj.git";
+        assert_eq!(parse(url).get_clone_url(), "git://example.org/proj.git");
+    }
+
+    #[test]
+    fn test_git_url_fs_path() {
+        let url = "git://example.org/proj.git";
+        let mut expected_path = config::Config::default().get_base_path().clone();
+        expected_path.push("example.org");
+        expected_path.push("proj");
+        assert_eq!(
+            parse(url).get_fs_path(&config::Config::default()),
+            expected_path
+        );
     }
 
     #[test]
@@ -230,12 +720,15 @@ mod tests {
         let expected_val = Repo {
             url: RepoUrl::Short(ShortHandUrl {
                 is_https: false,
+                user: Some("git".to_string()),
                 domain: "github.com".to_string(),
+                ssh_port: None,
+                https_port: None,
                 path: "user/repo.git".to_string(),
             }),
         };
 
-        assert_eq!(Repo::parse(url), expected_val);
+        assert_eq!(parse(url), expected_val);
     }
 
     #[test]
@@ -244,58 +737,143 @@ mod tests {
         let expected_val = Repo {
             url: RepoUrl::Short(ShortHandUrl {
                 is_https: false,
+                user: None,
                 domain: "github.com".to_string(),
+                ssh_port: None,
+                https_port: None,
                 path: "user/repo.git".to_string(),
             }),
         };
 
-        assert_eq!(Repo::parse(url), expected_val);
+        assert_eq!(parse(url), expected_val);
     }
 
     #[test]
     fn test_short_ssh_github_clone_url() {
         let url = "git@github.com:user/repo.git";
 
+        assert_eq!(parse(url).get_clone_url(), "git@github.com:user/repo.git");
+    }
+
+    #[test]
+    fn test_short_ssh_custom_user() {
+        let url = "aur@aur.archlinux.org:pkg.git";
+        let expected_val = Repo {
+            url: RepoUrl::Short(ShortHandUrl {
+                is_https: false,
+                user: Some("aur".to_string()),
+                domain: "aur.archlinux.org".to_string(),
+                ssh_port: None,
+                https_port: None,
+                path: "pkg.git".to_string(),
+            }),
+        };
+
+        assert_eq!(parse(url), expected_val);
+    }
+
+    #[test]
+    fn test_short_ssh_custom_user_clone_url() {
+        let url = "aur@aur.archlinux.org:pkg.git";
+        assert_eq!(parse(url).get_clone_url(), "aur@aur.archlinux.org:pkg.git");
+    }
+
+    #[test]
+    fn test_short_ssh_configured_user_clone_url() {
+        let mut conf = config::Config::default();
+        conf.set_ssh_user("aur.archlinux.org", "aur".to_string());
+        conf.set_ssh_user("github.com", "me".to_string());
+
+        let repo = Repo::parse("aur.archlinux.org:pkg", &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "aur@aur.archlinux.org:pkg");
+        let repo = Repo::parse("user/repo", &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "me@github.com:user/repo");
+        let repo = Repo::parse("git@aur.archlinux.org:pkg", &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "git@aur.archlinux.org:pkg");
+    }
+
+    #[test]
+    fn test_short_ssh_port_clone_url() {
+        let mut conf = config::Config::default();
+        conf.set_ssh_user("git.example.org", "gitea".to_string());
+        conf.set_ssh_port("git.example.org", 2222);
+        let repo = Repo::parse("git.example.org:team/repo", &conf).unwrap();
         assert_eq!(
-            Repo::parse(url).get_clone_url(),
-            "git@github.com:user/repo.git"
+            repo.get_clone_url(),
+            "ssh://gitea@git.example.org:2222/team/repo"
+        );
+    }
+
+    #[test]
+    fn test_short_https_port_clone_url() {
+        let mut conf = config::Config::default();
+        conf.set_domain_is_https("gitlab.corp.example", true);
+        conf.set_https_port("gitlab.corp.example", 8443);
+        let repo = Repo::parse("gitlab.corp.example:group/sub/repo", &conf).unwrap();
+        assert_eq!(
+            repo.get_clone_url(),
+            "https://gitlab.corp.example:8443/group/sub/repo"
+        );
+    }
+
+    #[test]
+    fn test_forced_ssh_ignores_https_port() {
+        let mut conf = config::Config::default();
+        conf.set_domain_is_https("git.example.org", true);
+        conf.set_https_port("git.example.org", 8443);
+        let repo = Repo::parse("git.example.org:team/repo", &conf).unwrap();
+        assert_eq!(
+            repo.get_clone_url(),
+            "https://git.example.org:8443/team/repo"
+        );
+        let repo = Repo::parse("git@git.example.org:team/repo", &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "git@git.example.org:team/repo");
+
+        conf.set_ssh_port("git.example.org", 2222);
+        let repo = Repo::parse("git@git.example.org:team/repo", &conf).unwrap();
+        assert_eq!(
+            repo.get_clone_url(),
+            "ssh://git@git.example.org:2222/team/repo"
         );
     }
 
     #[test]
     fn test_simple_url() {
         let url = "user/repo";
-        let config = config::get_config();
+        let config = config::Config::default();
         let expected_val = Repo {
             url: RepoUrl::Short(ShortHandUrl {
-                is_https: config.get_is_https(),
+                is_https: config.get_is_https_for(config.get_domain()),
+                user: None,
                 domain: config.get_domain().clone(),
+                ssh_port: None,
+                https_port: None,
                 path: "user/repo".to_string(),
             }),
         };
 
-        assert_eq!(Repo::parse(url), expected_val);
+        assert_eq!(parse(url), expected_val);
     }
 
     #[test]
     fn test_simple_url_domain() {
         let url = "user/repo";
         assert_eq!(
-            Repo::parse(url).get_domain(),
-            config::get_config().get_domain().clone()
+            parse(url).get_domain(),
+            config::Config::default().get_domain().clone()
         );
     }
 
     #[test]
     fn test_simple_url_get_clone_url() {
         let url = "user/repo";
-        assert_eq!(Repo::parse(url).get_clone_url(), "git@github.com:user/repo");
+        assert_eq!(parse(url).get_clone_url(), "git@github.com:user/repo");
     }
 
     #[test]
     fn test_simple_url_get_clone_url_https() {
         let url = "user/repo";
-        let mut this_repo = Repo::parse(url);
+        let mut this_repo = parse(url);
         match this_repo.url {
             RepoUrl::Short(ref mut short) => {
                 short.is_https = true;
@@ -307,30 +885,316 @@ mod tests {
         assert_eq!(this_repo.get_clone_url(), "https://github.com/user/repo");
     }
 
+    #[test]
+    fn test_fallback_url() {
+        let conf = config::Config::default();
+        assert_eq!(
+            parse("user/repo").get_fallback_url(&conf),
+            Some("https://github.com/user/repo".to_string())
+        );
+        assert_eq!(
+            parse("https://github.com/user/repo.git").get_fallback_url(&conf),
+            Some("git@github.com:user/repo.git".to_string())
+        );
+        assert_eq!(
+            parse("ssh://aur@aur.archlinux.org/user/repo.git").get_fallback_url(&conf),
+            Some("https://aur.archlinux.org/user/repo.git".to_string())
+        );
+        assert_eq!(
+            parse("aur@aur.archlinux.org:repo").get_fallback_url(&conf),
+            Some("https://aur.archlinux.org/repo".to_string())
+        );
+        assert_eq!(
+            parse("git://github.com/user/repo").get_fallback_url(&conf),
+            None
+        );
+        assert_eq!(
+            parse("/srv/mirrors/acme/tool.git").get_fallback_url(&conf),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fallback_url_keeps_ssh_user_and_port() {
+        let mut conf = config::Config::default();
+        conf.set_ssh_user("git.example.org", "gitea".to_string());
+        let repo = Repo {
+            url: RepoUrl::Short(ShortHandUrl {
+                is_https: true,
+                user: conf.get_ssh_user("git.example.org").cloned(),
+                domain: "git.example.org".to_string(),
+                ssh_port: None,
+                https_port: Some(8443),
+                path: "owner/repo".to_string(),
+            }),
+        };
+        assert_eq!(
+            repo.get_https_url(&conf),
+            Some("https://git.example.org:8443/owner/repo".to_string())
+        );
+        assert_eq!(
+            repo.get_fallback_url(&conf),
+            Some("gitea@git.example.org:owner/repo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fallback_url_uses_domain_settings() {
+        let mut conf = config::Config::default();
+        conf.set_ssh_user("git.example.org", "gitea".to_string());
+        conf.set_ssh_port("git.example.org", 2222);
+        conf.set_https_port("git.example.org", 8443);
+        let repo = Repo::parse("https://git.example.org/owner/repo.git", &conf).unwrap();
+        assert_eq!(
+            repo.get_fallback_url(&conf),
+            Some("ssh://gitea@git.example.org:2222/owner/repo.git".to_string())
+        );
+        let repo = Repo::parse("ssh://git@git.example.org/owner/repo.git", &conf).unwrap();
+        assert_eq!(
+            repo.get_fallback_url(&conf),
+            Some("https://git.example.org:8443/owner/repo.git".to_string())
+        );
+        let repo = Repo::parse("git://git.example.org/owner/repo.git", &conf).unwrap();
+        assert_eq!(
+            repo.get_ssh_url(&conf),
+            Some("ssh://gitea@git.example.org:2222/owner/repo.git".to_string())
+        );
+    }
+
     #[test]
     fn test_simple_url_path() {
         let url = "user/repo";
-        assert_eq!(Repo::parse(url).get_sub_path(), "user/repo");
+        assert_eq!(parse(url).get_sub_path(), "user/repo");
     }
 
     #[test]
     fn test_simple_url_multiple() {
         let url = "user/repo/dir1/dir2";
-        let config = config::get_config();
+        let config = config::Config::default();
         let expected_val = Repo {
             url: RepoUrl::Short(ShortHandUrl {
-                is_https: config.get_is_https(),
+                is_https: config.get_is_https_for(config.get_domain()),
+                user: None,
                 domain: config.get_domain().clone(),
+                ssh_port: None,
+                https_port: None,
                 path: "user/repo/dir1/dir2".to_string(),
             }),
         };
 
-        assert_eq!(Repo::parse(url), expected_val);
+        assert_eq!(parse(url), expected_val);
     }
 
     #[test]
     fn test_simple_url_multiple_path() {
         let url = "user/repo/dir1/dir2";
-        assert_eq!(Repo::parse(url).get_sub_path(), "user/repo/dir1/dir2");
+        assert_eq!(parse(url).get_sub_path(), "user/repo/dir1/dir2");
+    }
+
+    #[test]
+    fn test_file_url() {
+        let url = "file:///srv/mirrors/acme/tool.git";
+        let repo = parse(url);
+        assert_eq!(repo.get_clone_url(), "file:///srv/mirrors/acme/tool.git");
+        assert_eq!(repo.get_sub_path(), "srv/mirrors/acme/tool.git");
+    }
+
+    #[test]
+    fn test_local_path() {
+        let url = "/srv/mirrors/acme/tool.git";
+        let repo = parse(url);
+        assert_eq!(repo.get_clone_url(), "/srv/mirrors/acme/tool.git");
+        assert_eq!(
+            repo.get_domain(),
+            config::Config::default().get_local_domain().clone()
+        );
+        assert_eq!(repo.get_sub_path(), "srv/mirrors/acme/tool.git");
+    }
+
+    #[test]
+    fn test_local_path_fs_path() {
+        let url = "/srv/mirrors/acme/tool.git";
+        let conf = config::Config::default();
+        let mut expected_path = conf.get_base_path().clone();
+        expected_path.push(conf.get_local_domain());
+        expected_path.push("srv/mirrors/acme/tool");
+        assert_eq!(
+            parse(url).get_fs_path(&config::Config::default()),
+            expected_path
+        );
+    }
+
+    #[test]
+    fn test_repo_name() {
+        assert_eq!(parse("user/repo").get_repo_name(), "repo");
+        assert_eq!(
+            parse("git@github.com:user/repo.git").get_repo_name(),
+            "repo"
+        );
+        assert_eq!(
+            parse("https://github.com/user/repo.git.git").get_repo_name(),
+            "repo.git"
+        );
+    }
+
+    #[test]
+    fn test_shorthand_strips_one_git_suffix() {
+        let conf = config::Config::default();
+        let repo = parse("https://github.com/user/repo.git.git");
+        assert_eq!(repo.get_shorthand(&conf), "user/repo.git");
+    }
+
+    #[test]
+    fn test_relative_local_path() {
+        let url = "./mirrors/../tool.git";
+        let mut expected_path = std::env::current_dir().unwrap();
+        expected_path.push("tool.git");
+        assert_eq!(parse(url).get_clone_url(), expected_path.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_local_path_mapping() {
+        let conf = get_local_config();
+        let local =
+            LocalPath::new(PathBuf::from("/srv/mirrors/acme/tool.git"), false, &conf).unwrap();
+        assert_eq!(local.domain, "mirrors");
+        assert_eq!(local.sub_path, "acme/tool.git");
+    }
+
+    #[test]
+    fn test_local_path_longest_mapping() {
+        let conf = get_local_config();
+        let local =
+            LocalPath::new(PathBuf::from("/srv/mirrors/vendor/lib.git"), true, &conf).unwrap();
+        assert_eq!(local.domain, "vendor-mirrors");
+        assert_eq!(local.sub_path, "lib.git");
+    }
+
+    #[test]
+    fn test_local_from_tree() {
+        let conf = get_local_config();
+        let local = LocalPath::from_tree("mirrors", "acme/tool", &conf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(local.path, PathBuf::from("/srv/mirrors/acme/tool"));
+        assert_eq!(local.sub_path, "acme/tool");
+    }
+
+    #[test]
+    fn test_local_from_tree_bare_repo() {
+        let (test_dir, mut conf) = get_test_tree("bare-repo");
+        let mirrors = test_dir.join("mirrors");
+        fs::create_dir_all(mirrors.join("acme/tool.git")).unwrap();
+        fs::create_dir_all(mirrors.join("acme/lib")).unwrap();
+        conf.add_local_path(mirrors.clone(), "mirrors".to_string());
+
+        let repo = Repo::parse("mirrors:acme/tool", &conf).unwrap();
+        assert_eq!(
+            repo.get_clone_url(),
+            mirrors.join("acme/tool.git").to_str().unwrap()
+        );
+        assert_eq!(repo.get_shorthand(&conf), "mirrors:acme/tool");
+        let repo = Repo::parse("mirrors:acme/lib", &conf).unwrap();
+        assert_eq!(
+            repo.get_clone_url(),
+            mirrors.join("acme/lib").to_str().unwrap()
+        );
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_local_from_tree_default_domain() {
+        let conf = get_local_config();
+        let local_domain = conf.get_local_domain().clone();
+        let local = LocalPath::from_tree(&local_domain, "srv/repos/tool/", &conf)
+            .unwrap()
+            .unwrap();
+        assert_eq!(local.path, PathBuf::from("/srv/repos/tool"));
+        assert_eq!(local.domain, local_domain);
+    }
+
+    #[test]
+    fn test_local_from_tree_unknown_domain() {
+        let conf = get_local_config();
+        assert_eq!(
+            LocalPath::from_tree("github.com", "user/repo", &conf).unwrap(),
+            None
+        );
+    }
+
+    fn get_test_tree(name: &str) -> (PathBuf, config::Config) {
+        let test_dir = test_util::get_test_dir(&format!("repo-{}", name));
+        let mut conf = config::Config::default();
+        conf.set_base_path(test_dir.join("tree"));
+        (test_dir, conf)
+    }
+
+    fn create_clone(dir: &Path, git_config: &str) {
+        let status = std::process::Command::new("git")
+            .args(["init", "-q"])
+            .arg(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let config_path = dir.join(".git/config");
+        let config = fs::read_to_string(&config_path).unwrap() + git_config;
+        fs::write(&config_path, config).unwrap();
+    }
+
+    #[test]
+    fn test_from_fs_path_in_tree() {
+        let (test_dir, mut conf) = get_test_tree("in-tree");
+        conf.set_domain_layout(
+            "gitlab.com",
+            Layout::parse("{domain}/{owner}--{repo}").unwrap(),
+        );
+        let clone_dir = test_dir.join("tree/github.com/allonsy/gclone");
+        create_clone(&clone_dir, "");
+        fs::create_dir(clone_dir.join("src")).unwrap();
+        create_clone(&test_dir.join("tree/gitlab.com/group--repo"), "");
+
+        let repo = Repo::from_fs_path(&clone_dir.join("src"), &conf).unwrap();
+        assert_eq!(repo.get_shorthand(&conf), "allonsy/gclone");
+        assert_eq!(repo.get_clone_url(), "git@github.com:allonsy/gclone");
+        let repo =
+            Repo::from_fs_path(&test_dir.join("tree/gitlab.com/group--repo"), &conf).unwrap();
+        assert_eq!(repo.get_shorthand(&conf), "gitlab.com:group/repo");
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_fs_path_prefers_matching_remote() {
+        let (test_dir, conf) = get_test_tree("matching-remote");
+        let clone_dir = test_dir.join("tree/local/srv/acme/tool");
+        create_clone(
+            &clone_dir,
+            "[core]\n\tbare = false\n[remote \"origin\"]\n\turl = /srv/acme/tool.git\n",
+        );
+
+        let repo = Repo::from_fs_path(&clone_dir, &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "/srv/acme/tool.git");
+        assert_eq!(repo.get_shorthand(&conf), "local:srv/acme/tool");
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_fs_path_outside_tree() {
+        let (test_dir, conf) = get_test_tree("outside-tree");
+        let clone_dir = test_dir.join("elsewhere/repo");
+        create_clone(
+            &clone_dir,
+            "[remote \"upstream\"]\n\turl = git@github.com:allonsy/gclone.git\n\
+             [remote \"origin\"]\n\turl = https://gitlab.com/group/repo.git\n",
+        );
+
+        let repo = Repo::from_fs_path(&clone_dir, &conf).unwrap();
+        assert_eq!(repo.get_clone_url(), "https://gitlab.com/group/repo.git");
+        assert_eq!(repo.get_shorthand(&conf), "gitlab.com:group/repo");
+
+        fs::create_dir_all(test_dir.join("not-a-repo")).unwrap();
+        assert!(Repo::from_fs_path(&test_dir.join("not-a-repo"), &conf).is_err());
+        create_clone(&test_dir.join("no-remote"), "");
+        assert!(Repo::from_fs_path(&test_dir.join("no-remote"), &conf).is_err());
+        fs::remove_dir_all(&test_dir).unwrap();
     }
 }
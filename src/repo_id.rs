@@ -0,0 +1,226 @@
+use std::fmt;
+
+/// The identity of a repo, whatever the protocol of its url: the host (the
+/// pseudo-domain for local repos), the port if it is unusual, and the repo
+/// path without slashes at either end nor `.git` suffix. The default port of
+/// the protocol and the port configured for the domain aren't kept.
+///
+/// `allonsy/gclone`, `git@github.com:allonsy/gclone.git` and
+/// `https://github.com/allonsy/gclone` all have the same identity.
+#[derive(Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Debug)]
+pub struct RepoId {
+    host: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl RepoId {
+    pub(crate) fn new(host: &str, port: Option<u16>, path: &str) -> RepoId {
+        let components: Vec<&str> = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        let path = components.join("/");
+        RepoId {
+            host: host.to_lowercase(),
+            port,
+            path: path.strip_suffix(".git").unwrap_or(&path).to_string(),
+        }
+    }
+
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn get_port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl fmt::Display for RepoId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}:{}/{}", self.host, port, self.path),
+            None => write!(f, "{}/{}", self.host, self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RepoId;
+    use crate::config::Config;
+    use crate::repo::Repo;
+    use std::collections::{BTreeSet, HashSet};
+    use std::path::PathBuf;
+
+    fn get_id(url: &str) -> RepoId {
+        let mut conf = Config::default();
+        conf.add_local_path(PathBuf::from("/srv/mirrors"), "mirrors".to_string());
+        conf.set_ssh_port("gitlab.corp.example", 8443);
+        conf.set_https_port("gitlab.corp.example", 8443);
+        Repo::parse(url, &conf).unwrap().get_id(&conf)
+    }
+
+    fn assert_same_ids(urls: &[&str]) {
+        let expected = get_id(urls[0]);
+        for url in &urls[1..] {
+            assert_eq!(get_id(url), expected, "{} and {} differ", urls[0], url);
+        }
+    }
+
+    #[test]
+    fn test_same_repo_over_every_protocol() {
+        assert_same_ids(&[
+            "allonsy/gclone",
+            "allonsy/gclone.git",
+            "github.com:allonsy/gclone",
+            "git@github.com:allonsy/gclone",
+            "git@github.com:allonsy/gclone.git",
+            "https://github.com/allonsy/gclone",
+            "https://github.com/allonsy/gclone.git",
+            "https://github.com/allonsy/gclone/",
+            "https://GitHub.com/allonsy/gclone",
+            "https://github.com:443/allonsy/gclone",
+            "http://github.com/allonsy/gclone",
+            "ssh://git@github.com/allonsy/gclone.git",
+            "ssh://git@github.com:22/allonsy/gclone",
+            "git+ssh://git@github.com/allonsy/gclone.git",
+            "ssh+git://git@github.com:22/allonsy/gclone",
+            "git://github.com/allonsy/gclone.git",
+            "git://github.com:9418/allonsy/gclone",
+        ]);
+    }
+
+    #[test]
+    fn test_nested_paths() {
+        assert_same_ids(&[
+            "gitlab.com:group/sub/repo",
+            "git@gitlab.com:group/sub/repo.git",
+            "https://gitlab.com/group/sub/repo.git",
+            "ssh://git@gitlab.com/group//sub/repo",
+        ]);
+        assert_eq!(
+            get_id("gitlab.com:group/sub/repo").get_path(),
+            "group/sub/repo"
+        );
+    }
+
+    #[test]
+    fn test_local_repos() {
+        assert_same_ids(&[
+            "/srv/mirrors/acme/tool.git",
+            "/srv/mirrors/acme/tool",
+            "file:///srv/mirrors/acme/tool.git",
+            "mirrors:acme/tool",
+        ]);
+        let id = get_id("/srv/mirrors/acme/tool.git");
+        assert_eq!(id.get_host(), "mirrors");
+        assert_eq!(id.get_path(), "acme/tool");
+
+        assert_same_ids(&["/tmp/repo.git", "file:///tmp/repo"]);
+        assert_eq!(get_id("/tmp/repo").get_host(), "local");
+    }
+
+    #[test]
+    fn test_ports() {
+        assert_same_ids(&[
+            "gitlab.corp.example:group/repo",
+            "https://gitlab.corp.example:8443/group/repo.git",
+            "ssh://git@gitlab.corp.example:8443/group/repo",
+        ]);
+        assert_eq!(
+            get_id("https://gitlab.corp.example:8443/group/repo").get_port(),
+            None
+        );
+        assert_same_ids(&[
+            "git@example.org:group/repo",
+            "ssh://git@example.org:22/group/repo",
+            "git+ssh://git@example.org:22/group/repo",
+            "https://example.org:443/group/repo",
+            "http://example.org:80/group/repo",
+            "git://example.org:9418/group/repo",
+        ]);
+
+        assert_same_ids(&[
+            "ssh://git@example.org:2222/group/repo",
+            "git+ssh://git@example.org:2222/group/repo",
+            "ssh+git://example.org:2222/group/repo",
+        ]);
+        assert_eq!(
+            get_id("ssh://git@example.org:2222/group/repo").get_port(),
+            Some(2222)
+        );
+        let ids = [
+            get_id("ssh://git@example.org:2222/group/repo"),
+            get_id("git@example.org:group/repo"),
+            get_id("http://example.org:8080/group/repo"),
+            get_id("http://example.org:9090/group/repo"),
+            get_id("https://example.org:8443/group/repo"),
+        ];
+        for (index, id) in ids.iter().enumerate() {
+            for other_id in &ids[index + 1..] {
+                assert_ne!(id, other_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_different_repos() {
+        let ids = [
+            get_id("allonsy/gclone"),
+            get_id("fork/gclone"),
+            get_id("allonsy/gclone-fork"),
+            get_id("allonsy/gclone/sub"),
+            get_id("gitlab.com:allonsy/gclone"),
+            get_id("Allonsy/gclone"),
+            get_id("/tmp/allonsy/gclone"),
+        ];
+        for (index, id) in ids.iter().enumerate() {
+            for other_id in &ids[index + 1..] {
+                assert_ne!(id, other_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_and_ord() {
+        let urls = [
+            "https://gitlab.com/b/repo",
+            "allonsy/gclone",
+            "git@github.com:allonsy/gclone.git",
+            "gitlab.com:a/repo",
+            "https://github.com/allonsy/gclone",
+        ];
+        let hashed: HashSet<RepoId> = urls.iter().map(|url| get_id(url)).collect();
+        assert_eq!(hashed.len(), 3);
+
+        let sorted: Vec<String> = urls
+            .iter()
+            .map(|url| get_id(url))
+            .collect::<BTreeSet<RepoId>>()
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "github.com/allonsy/gclone",
+                "gitlab.com/a/repo",
+                "gitlab.com/b/repo"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            RepoId::new("GitLab.corp.example", Some(8443), "/group/repo.git/").to_string(),
+            "gitlab.corp.example:8443/group/repo"
+        );
+    }
+}
@@ -0,0 +1,629 @@
+use crate::cli::{self, Arg, Command, Flag, FlagValue, Values};
+use gclone::{Error, Result};
+
+pub static SHELLS: &[&str] = &["bash", "zsh", "fish"];
+pub static COMPLETION_SHELLS: &[&str] = &["bash", "zsh", "fish", "nushell", "powershell"];
+
+static POSIX_FUNCTION: &str = include_str!("../shell/gclone.sh");
+static FISH_FUNCTION: &str = include_str!("../shell/gclone.fish");
+
+/// Returns the script that `gclone init <shell>` prints: the `gclone` shell
+/// function followed by its completions.
+pub fn get_init_script(shell: &str) -> Result<String> {
+    match shell {
+        "bash" => Ok(format!("{}\n{}", POSIX_FUNCTION, get_bash_completion())),
+        "zsh" => Ok(format!(
+            "{}\n{}\nif (($+functions[compdef])); then\n    compdef _gclone gclone\nfi\n",
+            POSIX_FUNCTION,
+            get_zsh_functions()
+        )),
+        "fish" => Ok(format!("{}\n{}", FISH_FUNCTION, get_fish_completion())),
+        _ => Err(unsupported_shell(shell, SHELLS)),
+    }
+}
+
+/// Returns the completion script for `shell`, meant to be installed where the
+/// shell looks for completions. Nushell and PowerShell have no `gclone`
+/// function, so their completions are for `gclone-bin` itself.
+pub fn get_completions(shell: &str) -> Result<String> {
+    match shell {
+        "bash" => Ok(get_bash_completion()),
+        "zsh" => Ok(format!(
+            "#compdef gclone\n\n{}\n_gclone \"$@\"\n",
+            get_zsh_functions()
+        )),
+        "fish" => Ok(get_fish_completion()),
+        "nushell" => Ok(get_nushell_completion()),
+        "powershell" => Ok(get_powershell_completion()),
+        _ => Err(unsupported_shell(shell, COMPLETION_SHELLS)),
+    }
+}
+
+fn unsupported_shell(shell: &str, supported: &[&str]) -> Error {
+    Error::Usage(format!(
+        "Unsupported shell: {} (expected one of {})",
+        shell,
+        supported.join(", ")
+    ))
+}
+
+fn get_visible_flags(command: &Command) -> impl Iterator<Item = &Flag> {
+    command.flags.iter().filter(|flag| !flag.hidden)
+}
+
+fn get_flag_names(flag: &Flag) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(short) = flag.short {
+        names.push(format!("-{}", short));
+    }
+    names.push(format!("--{}", flag.long));
+    names
+}
+
+fn get_command_names() -> Vec<&'static str> {
+    cli::COMMANDS.iter().map(|command| command.name).collect()
+}
+
+fn get_bash_completion() -> String {
+    let mut script = String::new();
+    script.push_str("_gclone() {\n");
+    script.push_str(
+        "    local prev=${COMP_WORDS[COMP_CWORD-1]} command=gclone flags words repos i\n",
+    );
+    script.push_str("    # COMP_WORDBREAKS splits domain:path hints at the colon, so the word\n");
+    script.push_str("    # being completed is taken from the command line instead.\n");
+    script.push_str("    local cur=${COMP_LINE:0:COMP_POINT}\n");
+    script.push_str("    cur=${cur##*[[:space:]]}\n");
+    script.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+    script.push_str("        [[ ${COMP_WORDS[i]} == -- ]] && return\n");
+    script.push_str("    done\n");
+    script.push_str("    if ((COMP_CWORD > 1)); then\n");
+    script.push_str("        case ${COMP_WORDS[1]} in\n");
+    script.push_str(&format!(
+        "            {}) command=${{COMP_WORDS[1]}} ;;\n",
+        get_command_names().join("|")
+    ));
+    script.push_str("        esac\n");
+    script.push_str("    fi\n");
+
+    let mut value_flags: Vec<String> = get_visible_flags(&cli::MAIN)
+        .chain(cli::COMMANDS.iter().flat_map(get_visible_flags))
+        .filter(|flag| flag.value != FlagValue::None)
+        .flat_map(get_flag_names)
+        .collect();
+    value_flags.sort();
+    value_flags.dedup();
+    if !value_flags.is_empty() {
+        script.push_str("    case $prev in\n");
+        script.push_str(&format!("        {}) return ;;\n", value_flags.join("|")));
+        script.push_str("    esac\n");
+    }
+
+    script.push_str("    case $command in\n");
+    for command in std::iter::once(&cli::MAIN).chain(cli::COMMANDS) {
+        let flags: Vec<String> = get_visible_flags(command)
+            .flat_map(get_flag_names)
+            .collect();
+        script.push_str(&format!("        {})\n", command.name));
+        script.push_str(&format!("            flags=\"{}\"\n", flags.join(" ")));
+        if command.name == cli::MAIN.name {
+            script.push_str(&format!(
+                "            ((COMP_CWORD == 1)) && words=\"{}\"\n",
+                get_command_names().join(" ")
+            ));
+        }
+        match command.args.first().map(|arg| &arg.values) {
+            Some(Values::Repos) => script.push_str("            repos=1\n"),
+            Some(Values::Commands) => script.push_str(&format!(
+                "            words=\"{}\"\n",
+                get_command_names().join(" ")
+            )),
+            Some(Values::List(values)) => {
+                script.push_str(&format!("            words=\"{}\"\n", values.join(" ")))
+            }
+            Some(Values::None) | None => {}
+        }
+        script.push_str("            ;;\n");
+    }
+    script.push_str("    esac\n");
+
+    script.push_str("    if [[ $cur == -* ]]; then\n");
+    script.push_str("        COMPREPLY=($(compgen -W \"$flags\" -- \"$cur\"))\n");
+    script.push_str("        return\n");
+    script.push_str("    fi\n");
+    script.push_str("    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n");
+    script.push_str("    if [[ -n $repos ]]; then\n");
+    script.push_str("        local IFS=$'\\n'\n");
+    script.push_str(
+        "        COMPREPLY+=($(command gclone-bin complete -- \"$cur\" 2>/dev/null | cut -f 1))\n",
+    );
+    script.push_str("    fi\n");
+    script.push_str("    if [[ $cur == *:* && $COMP_WORDBREAKS == *:* ]]; then\n");
+    script.push_str("        local colon_prefix=${cur%\"${cur##*:}\"}\n");
+    script.push_str("        for i in \"${!COMPREPLY[@]}\"; do\n");
+    script.push_str("            COMPREPLY[i]=${COMPREPLY[i]#\"$colon_prefix\"}\n");
+    script.push_str("        done\n");
+    script.push_str("    fi\n");
+    script.push_str("    # Domains and directories are completed further, don't add a space.\n");
+    script.push_str("    for i in \"${COMPREPLY[@]}\"; do\n");
+    script.push_str("        if [[ $i == */ || $i == *: ]]; then\n");
+    script.push_str("            compopt -o nospace 2>/dev/null\n");
+    script.push_str("            break\n");
+    script.push_str("        fi\n");
+    script.push_str("    done\n");
+    script.push_str("}\n");
+    script.push_str("complete -F _gclone gclone\n");
+    script
+}
+
+fn escape_zsh(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn get_zsh_action(values: &Values) -> String {
+    match values {
+        Values::None => " ".to_string(),
+        Values::Repos => "_gclone_repos".to_string(),
+        Values::Commands => "_gclone_commands".to_string(),
+        Values::List(values) => format!("({})", values.join(" ")),
+    }
+}
+
+fn get_zsh_flag_spec(flag: &Flag) -> String {
+    let names = get_flag_names(flag);
+    let help = escape_zsh(flag.help);
+    let value_spec = match flag.value {
+        FlagValue::None => String::new(),
+        FlagValue::Required(name) | FlagValue::Optional(name) => {
+            format!(":{}: ", name.to_lowercase())
+        }
+    };
+    let suffixes: Vec<String> = match flag.value {
+        FlagValue::None => names,
+        _ => names
+            .iter()
+            .map(|name| {
+                if name.starts_with("--") {
+                    format!("{}=", name)
+                } else {
+                    format!("{}+", name)
+                }
+            })
+            .collect(),
+    };
+    if suffixes.len() == 1 {
+        format!("'{}[{}]{}'", suffixes[0], help, value_spec)
+    } else {
+        format!(
+            "'({})'{{{}}}'[{}]{}'",
+            get_flag_names(flag).join(" "),
+            suffixes.join(","),
+            help,
+            value_spec
+        )
+    }
+}
+
+fn get_zsh_arg_spec(index: usize, arg: &Arg, action: String) -> String {
+    let separator = if arg.required { ":" } else { "::" };
+    format!(
+        "'{}{}{}:{}'",
+        index + 1,
+        separator,
+        arg.name.to_lowercase(),
+        action
+    )
+}
+
+fn get_zsh_arguments(command: &Command, indent: &str) -> String {
+    let mut specs: Vec<String> = get_visible_flags(command).map(get_zsh_flag_spec).collect();
+    specs.extend(command.args.iter().enumerate().map(|(index, arg)| {
+        // Without a command, the first argument is either a command or a repo.
+        let action = if command.name == cli::MAIN.name && index == 0 {
+            "_gclone_first".to_string()
+        } else {
+            get_zsh_action(&arg.values)
+        };
+        get_zsh_arg_spec(index, arg, action)
+    }));
+    let mut arguments = format!("{}_arguments -s -S", indent);
+    for spec in specs {
+        arguments.push_str(&format!(" \\\n{}    {}", indent, spec));
+    }
+    arguments.push('\n');
+    arguments
+}
+
+fn get_zsh_functions() -> String {
+    let mut script = String::new();
+    script.push_str("_gclone_repos() {\n");
+    script.push_str("    local -a gclone_hints gclone_descriptions\n");
+    script.push_str("    local gclone_line\n");
+    script.push_str(
+        "    for gclone_line in \"${(@f)$(command gclone-bin complete -- \"$PREFIX\" 2>/dev/null)}\"; do\n",
+    );
+    script.push_str("        [[ -n $gclone_line ]] || continue\n");
+    script.push_str("        gclone_hints+=(\"${gclone_line%%$'\\t'*}\")\n");
+    script.push_str("        gclone_descriptions+=(\"${gclone_line#*$'\\t'}\")\n");
+    script.push_str("    done\n");
+    script.push_str("    compadd -U -S '' -d gclone_descriptions -a gclone_hints\n");
+    script.push_str("}\n\n");
+
+    script.push_str("_gclone_commands() {\n");
+    script.push_str("    local -a gclone_commands\n");
+    script.push_str("    gclone_commands=(\n");
+    for command in cli::COMMANDS {
+        script.push_str(&format!(
+            "        '{}:{}'\n",
+            command.name,
+            command.about.replace('\'', "'\\''")
+        ));
+    }
+    script.push_str("    )\n");
+    script.push_str("    _describe -t commands 'gclone command' gclone_commands\n");
+    script.push_str("}\n\n");
+
+    script.push_str("_gclone_first() {\n");
+    script.push_str("    _gclone_commands\n");
+    script.push_str("    _gclone_repos\n");
+    script.push_str("}\n\n");
+
+    script.push_str("_gclone() {\n");
+    script.push_str("    if ((CURRENT > 2)); then\n");
+    script.push_str("        case $words[2] in\n");
+    for command in cli::COMMANDS {
+        script.push_str(&format!("            {})\n", command.name));
+        script.push_str("                shift words\n");
+        script.push_str("                ((CURRENT--))\n");
+        script.push_str(&get_zsh_arguments(command, "                "));
+        script.push_str("                return\n");
+        script.push_str("                ;;\n");
+    }
+    script.push_str("        esac\n");
+    script.push_str("    fi\n");
+    script.push_str(&get_zsh_arguments(&cli::MAIN, "    "));
+    script.push_str("}\n");
+    script
+}
+
+fn escape_fish(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn get_fish_flag(condition: &str, flag: &Flag) -> String {
+    let mut line = format!("complete -c gclone -n '{}'", condition);
+    if let Some(short) = flag.short {
+        line.push_str(&format!(" -s {}", short));
+    }
+    line.push_str(&format!(" -l {}", flag.long));
+    if flag.value != FlagValue::None {
+        line.push_str(" -x");
+    }
+    line.push_str(&format!(" -d '{}'\n", escape_fish(flag.help)));
+    line
+}
+
+fn get_fish_args(condition: &str, command: &Command) -> String {
+    let values = match command.args.first() {
+        Some(arg) => match &arg.values {
+            Values::None => return String::new(),
+            Values::Repos => "(__gclone_repos)".to_string(),
+            Values::Commands => get_command_names().join(" "),
+            Values::List(values) => values.join(" "),
+        },
+        None => return String::new(),
+    };
+    format!("complete -c gclone -n '{}' -a '{}'\n", condition, values)
+}
+
+fn get_fish_completion() -> String {
+    let mut script = String::new();
+    script.push_str("function __gclone_repos\n");
+    script.push_str("    command gclone-bin complete -- (commandline -ct) 2>/dev/null\n");
+    script.push_str("end\n\n");
+    script.push_str("complete -c gclone -e\n");
+    script.push_str("complete -c gclone -f\n");
+
+    let main_condition = "__fish_use_subcommand";
+    for command in cli::COMMANDS {
+        script.push_str(&format!(
+            "complete -c gclone -n '{}' -a {} -d '{}'\n",
+            main_condition,
+            command.name,
+            escape_fish(command.about)
+        ));
+    }
+    for flag in get_visible_flags(&cli::MAIN) {
+        script.push_str(&get_fish_flag(main_condition, flag));
+    }
+    script.push_str(&get_fish_args(main_condition, &cli::MAIN));
+
+    for command in cli::COMMANDS {
+        let condition = format!("__fish_seen_subcommand_from {}", command.name);
+        for flag in get_visible_flags(command) {
+            script.push_str(&get_fish_flag(&condition, flag));
+        }
+        script.push_str(&get_fish_args(&condition, command));
+    }
+    script
+}
+
+fn escape_nushell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn get_nushell_completer(command: &Command, index: usize) -> Option<String> {
+    // Without a command, the first argument is either a command or a repo.
+    if command.name == cli::MAIN.name && index == 0 {
+        return Some("nu-complete gclone first".to_string());
+    }
+    let arg = &command.args[index];
+    match arg.values {
+        Values::None => None,
+        Values::Repos => Some("nu-complete gclone repos".to_string()),
+        Values::Commands => Some("nu-complete gclone commands".to_string()),
+        Values::List(_) => Some(format!(
+            "nu-complete gclone {} {}",
+            command.name,
+            arg.name.to_lowercase()
+        )),
+    }
+}
+
+fn get_nushell_extern(name: &str, command: &Command) -> String {
+    let mut script = format!("extern \"{}\" [\n", name);
+    // gclone-bin checks for missing arguments itself.
+    for (index, arg) in command.args.iter().enumerate() {
+        let completer = get_nushell_completer(command, index)
+            .map(|completer| format!("@\"{}\"", completer))
+            .unwrap_or_default();
+        script.push_str(&format!(
+            "    {}?: string{}  # {}\n",
+            arg.name.to_lowercase(),
+            completer,
+            arg.help
+        ));
+    }
+    // Nushell adds --help to every command.
+    for flag in get_visible_flags(command).filter(|flag| flag.long != "help") {
+        let short = flag
+            .short
+            .map(|short| format!("(-{})", short))
+            .unwrap_or_default();
+        let value = match flag.value {
+            FlagValue::None => "",
+            FlagValue::Required(_) | FlagValue::Optional(_) => ": string",
+        };
+        script.push_str(&format!(
+            "    --{}{}{}  # {}\n",
+            flag.long, short, value, flag.help
+        ));
+    }
+    if let Some(trailing) = &command.trailing {
+        script.push_str(&format!(
+            "    ...{}: string  # {}\n",
+            trailing.name.to_lowercase(),
+            trailing.help
+        ));
+    }
+    script.push_str("]\n");
+    script
+}
+
+fn get_nushell_completion() -> String {
+    let mut script = String::new();
+    script.push_str("def \"nu-complete gclone repos\" [context: string] {\n");
+    script.push_str("    let prefix = ($context | split row \" \" | last)\n");
+    script.push_str(
+        "    ^gclone-bin complete -- $prefix | lines | parse \"{value}\\t{description}\"\n",
+    );
+    script.push_str("}\n\n");
+
+    script.push_str("def \"nu-complete gclone commands\" [] {\n");
+    script.push_str("    [\n");
+    for command in cli::COMMANDS {
+        script.push_str(&format!(
+            "        {{value: \"{}\", description: \"{}\"}}\n",
+            command.name,
+            escape_nushell(command.about)
+        ));
+    }
+    script.push_str("    ]\n");
+    script.push_str("}\n\n");
+
+    script.push_str("def \"nu-complete gclone first\" [context: string] {\n");
+    script
+        .push_str("    nu-complete gclone commands | append (nu-complete gclone repos $context)\n");
+    script.push_str("}\n\n");
+
+    for command in cli::COMMANDS {
+        for (index, arg) in command.args.iter().enumerate() {
+            if let (Values::List(values), Some(completer)) =
+                (&arg.values, get_nushell_completer(command, index))
+            {
+                let quoted: Vec<String> = values
+                    .iter()
+                    .map(|value| format!("\"{}\"", value))
+                    .collect();
+                script.push_str(&format!("def \"{}\" [] {{\n", completer));
+                script.push_str(&format!("    [{}]\n", quoted.join(" ")));
+                script.push_str("}\n\n");
+            }
+        }
+    }
+
+    script.push_str(&get_nushell_extern("gclone-bin", &cli::MAIN));
+    for command in cli::COMMANDS {
+        script.push('\n');
+        script.push_str(&get_nushell_extern(
+            &format!("gclone-bin {}", command.name),
+            command,
+        ));
+    }
+    script
+}
+
+fn escape_powershell(text: &str) -> String {
+    text.replace('\'', "''")
+}
+
+fn get_powershell_items(items: &[(String, &str)], indent: &str) -> String {
+    let mut script = String::from("@(\n");
+    for (name, help) in items {
+        script.push_str(&format!(
+            "{}    @{{ Name = '{}'; Help = '{}' }}\n",
+            indent,
+            escape_powershell(name),
+            escape_powershell(help)
+        ));
+    }
+    script.push_str(&format!("{})", indent));
+    script
+}
+
+fn get_powershell_completion() -> String {
+    let command_items: Vec<(String, &str)> = cli::COMMANDS
+        .iter()
+        .map(|command| (command.name.to_string(), command.about))
+        .collect();
+    let quoted_commands: Vec<String> = get_command_names()
+        .iter()
+        .map(|name| format!("'{}'", name))
+        .collect();
+    let mut value_flags: Vec<String> = get_visible_flags(&cli::MAIN)
+        .chain(cli::COMMANDS.iter().flat_map(get_visible_flags))
+        .filter(|flag| flag.value != FlagValue::None)
+        .flat_map(get_flag_names)
+        .map(|name| format!("'{}'", name))
+        .collect();
+    value_flags.sort();
+    value_flags.dedup();
+
+    let mut script = String::new();
+    script
+        .push_str("Register-ArgumentCompleter -Native -CommandName 'gclone-bin' -ScriptBlock {\n");
+    script.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n\n");
+    script.push_str("    $previous = @($commandAst.CommandElements | Select-Object -Skip 1 |\n");
+    script.push_str("        Where-Object { $_.Extent.EndOffset -lt $cursorPosition } |\n");
+    script.push_str("        ForEach-Object { $_.ToString() })\n");
+    script.push_str("    if ($previous -contains '--') { return }\n");
+    script.push_str(&format!(
+        "    if ($previous.Count -gt 0 -and @({}) -contains $previous[-1]) {{ return }}\n",
+        value_flags.join(", ")
+    ));
+    script.push_str("    $command = 'gclone'\n");
+    script.push_str(&format!(
+        "    if ($previous.Count -gt 0 -and @({}) -contains $previous[0]) {{\n",
+        quoted_commands.join(", ")
+    ));
+    script.push_str("        $command = $previous[0]\n");
+    script.push_str("    }\n\n");
+
+    script.push_str("    $flags = @()\n");
+    script.push_str("    $values = @()\n");
+    script.push_str("    $repos = $false\n");
+    script.push_str("    switch ($command) {\n");
+    for command in std::iter::once(&cli::MAIN).chain(cli::COMMANDS) {
+        script.push_str(&format!("        '{}' {{\n", command.name));
+        let flag_items: Vec<(String, &str)> = get_visible_flags(command)
+            .flat_map(|flag| {
+                get_flag_names(flag)
+                    .into_iter()
+                    .map(move |name| (name, flag.help))
+            })
+            .collect();
+        if !flag_items.is_empty() {
+            script.push_str(&format!(
+                "            $flags = {}\n",
+                get_powershell_items(&flag_items, "            ")
+            ));
+        }
+        if command.name == cli::MAIN.name {
+            script.push_str("            if ($previous.Count -eq 0) {\n");
+            script.push_str(&format!(
+                "                $values = {}\n",
+                get_powershell_items(&command_items, "                ")
+            ));
+            script.push_str("            }\n");
+        }
+        match command.args.first().map(|arg| &arg.values) {
+            Some(Values::Repos) => script.push_str("            $repos = $true\n"),
+            Some(Values::Commands) => script.push_str(&format!(
+                "            $values = {}\n",
+                get_powershell_items(&command_items, "            ")
+            )),
+            Some(Values::List(values)) => {
+                let items: Vec<(String, &str)> = values
+                    .iter()
+                    .map(|value| (value.to_string(), *value))
+                    .collect();
+                script.push_str(&format!(
+                    "            $values = {}\n",
+                    get_powershell_items(&items, "            ")
+                ));
+            }
+            Some(Values::None) | None => {}
+        }
+        script.push_str("        }\n");
+    }
+    script.push_str("    }\n\n");
+
+    script.push_str("    if ($wordToComplete.StartsWith('-')) {\n");
+    script.push_str("        $flags | Where-Object { $_.Name.StartsWith($wordToComplete) } | ForEach-Object {\n");
+    script.push_str("            [System.Management.Automation.CompletionResult]::new($_.Name, $_.Name, 'ParameterName', $_.Help)\n");
+    script.push_str("        }\n");
+    script.push_str("        return\n");
+    script.push_str("    }\n");
+    script.push_str(
+        "    $values | Where-Object { $_.Name.StartsWith($wordToComplete) } | ForEach-Object {\n",
+    );
+    script.push_str("        [System.Management.Automation.CompletionResult]::new($_.Name, $_.Name, 'ParameterValue', $_.Help)\n");
+    script.push_str("    }\n");
+    script.push_str("    if ($repos) {\n");
+    script.push_str(
+        "        & gclone-bin complete '--' $wordToComplete 2>$null | ForEach-Object {\n",
+    );
+    script.push_str("            $hint, $description = $_ -split \"`t\", 2\n");
+    script.push_str("            [System.Management.Automation.CompletionResult]::new($hint, $description, 'ParameterValue', $hint)\n");
+    script.push_str("        }\n");
+    script.push_str("    }\n");
+    script.push_str("}\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_completions, get_init_script};
+
+    #[test]
+    fn test_init_scripts() {
+        for shell in super::SHELLS {
+            let script = get_init_script(shell).unwrap();
+            assert!(script.contains("GCLONE_CD_FILE"));
+            assert!(script.contains("gclone-bin complete"));
+            assert!(script.contains("nocd"));
+            assert!(!script.contains("match-prefix"));
+        }
+        assert!(get_init_script("tcsh").is_err());
+    }
+
+    #[test]
+    fn test_completions() {
+        for shell in super::COMPLETION_SHELLS {
+            let script = get_completions(shell).unwrap();
+            assert!(script.contains("gclone-bin complete"));
+            for flag in ["nocd", "local", "workspace", "version"] {
+                assert!(script.contains(flag));
+            }
+            for command in super::cli::COMMANDS {
+                assert!(script.contains(command.name));
+            }
+            assert!(!script.contains("GCLONE_CD_FILE"));
+            assert!(!script.contains("match-prefix"));
+        }
+        assert!(get_completions("tcsh").is_err());
+    }
+}
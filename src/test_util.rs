@@ -0,0 +1,14 @@
+//! Helpers shared by the tests of the library and of the binary.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns a fresh, empty directory for the test `name`, unique to this test
+/// process. Symlinks in its path are resolved, so that it can be compared with
+/// canonicalized paths.
+pub fn get_test_dir(name: &str) -> PathBuf {
+    let test_dir = std::env::temp_dir().join(format!("gclone-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&test_dir);
+    fs::create_dir_all(&test_dir).unwrap();
+    fs::canonicalize(&test_dir).unwrap()
+}
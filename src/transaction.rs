@@ -0,0 +1,120 @@
+use crate::interrupt::InterruptGuard;
+use gclone::{Error, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The filesystem changes of a clone, undone when the transaction is dropped
+/// without being committed.
+///
+/// While a transaction is open, SIGINT and SIGTERM don't kill gclone but are
+/// recorded, so that whoever runs git can stop it and return an error, which
+/// drops (and rolls back) the transaction.
+pub struct Transaction {
+    created: Vec<PathBuf>,
+    committed: bool,
+    interrupt_guard: InterruptGuard,
+}
+
+impl Transaction {
+    pub fn new() -> Result<Transaction> {
+        Ok(Transaction {
+            created: Vec::new(),
+            committed: false,
+            interrupt_guard: InterruptGuard::new()?,
+        })
+    }
+
+    /// Creates `path` and its missing parents, recording every directory
+    /// that didn't exist before.
+    pub fn create_dir_all(&mut self, path: &Path) -> Result<()> {
+        let mut missing = Vec::new();
+        let mut cur_path = path;
+        while !cur_path.exists() {
+            missing.push(cur_path.to_path_buf());
+            match cur_path.parent() {
+                Some(parent) => cur_path = parent,
+                None => break,
+            }
+        }
+
+        for dir in missing.into_iter().rev() {
+            fs::create_dir(&dir).map_err(|err| {
+                Error::Fs("Unable to create directory structure".to_string(), err)
+            })?;
+            self.created.push(dir);
+        }
+        Ok(())
+    }
+
+    /// Records a path that is about to be created by someone else, like the
+    /// directory git clones into.
+    pub fn record(&mut self, path: &Path) {
+        self.created.push(path.to_path_buf());
+    }
+
+    pub fn get_interrupt_guard(&self) -> &InterruptGuard {
+        &self.interrupt_guard
+    }
+
+    /// Keeps the recorded paths.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+
+    fn rollback(&mut self) {
+        for path in self.created.drain(..).rev() {
+            if path.is_dir() {
+                let _ = fs::remove_dir_all(&path);
+            } else if path.exists() {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transaction;
+    use crate::test_util::get_test_dir;
+    use std::fs;
+
+    #[test]
+    fn test_rollback_on_drop() {
+        let test_dir = get_test_dir("transaction-rollback");
+        let existing = test_dir.join("existing");
+        fs::create_dir(&existing).unwrap();
+
+        {
+            let mut transaction = Transaction::new().unwrap();
+            transaction
+                .create_dir_all(&existing.join("owner/sub"))
+                .unwrap();
+            let clone_dir = existing.join("owner/sub/repo");
+            transaction.record(&clone_dir);
+            fs::create_dir_all(clone_dir.join(".git")).unwrap();
+        }
+
+        assert!(existing.exists());
+        assert!(!existing.join("owner").exists());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit() {
+        let test_dir = get_test_dir("transaction-commit");
+        let mut transaction = Transaction::new().unwrap();
+        transaction.create_dir_all(&test_dir.join("owner")).unwrap();
+        transaction.commit();
+
+        assert!(test_dir.join("owner").exists());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
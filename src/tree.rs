@@ -0,0 +1,98 @@
+use crate::config;
+use crate::repo::Repo;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the clones found under the base paths of every workspace, with
+/// their repo, sorted by repo. Directories containing a `.git` are clones and
+/// aren't searched any further. Hidden directories (like the temporary
+/// directories of clones in progress) are skipped, and so are clones that
+/// can't be mapped back to a repo.
+pub fn find_repos(conf: &config::Config) -> Vec<(PathBuf, Repo)> {
+    let mut clone_dirs = Vec::new();
+    for base_path in conf.get_all_base_paths() {
+        find_clone_dirs(base_path, &mut clone_dirs);
+    }
+    clone_dirs.sort();
+    clone_dirs.dedup();
+
+    let mut repos: Vec<(PathBuf, Repo)> = clone_dirs
+        .into_iter()
+        .filter_map(|dir| {
+            let repo = Repo::from_fs_path(&dir, conf).ok()?;
+            Some((dir, repo))
+        })
+        .collect();
+    repos.sort_by_key(|(dir, repo)| (repo.get_id(conf), dir.clone()));
+    repos
+}
+
+fn find_clone_dirs(dir: &Path, clone_dirs: &mut Vec<PathBuf>) {
+    if dir.join(".git").exists() {
+        clone_dirs.push(dir.to_path_buf());
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let is_dir = entry
+            .file_type()
+            .map(|file_type| file_type.is_dir())
+            .unwrap_or(false);
+        let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if is_dir && !is_hidden {
+            find_clone_dirs(&entry.path(), clone_dirs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_repos;
+    use crate::config::Config;
+    use crate::test_util;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_find_repos() {
+        let test_dir = test_util::get_test_dir("tree");
+        let tree = test_dir.join("tree");
+        let work = test_dir.join("work");
+        for clone_dir in [
+            tree.join("github.com/allonsy/gclone"),
+            tree.join("github.com/allonsy/gclone/vendor/lib"),
+            tree.join("gitlab.com/group/sub/repo"),
+            tree.join("github.com/allonsy/.gclone-fork.gclone-42"),
+            work.join("github.com/acme/tool"),
+        ] {
+            fs::create_dir_all(clone_dir.join(".git")).unwrap();
+        }
+        fs::create_dir_all(tree.join("github.com/empty/dir")).unwrap();
+
+        let mut conf = Config::default();
+        conf.set_base_path(tree.clone());
+        conf.add_workspace("work", work.clone(), &["github.com"]);
+        let repos: Vec<(PathBuf, String)> = find_repos(&conf)
+            .into_iter()
+            .map(|(dir, repo)| (dir, repo.get_shorthand(&conf)))
+            .collect();
+        assert_eq!(
+            repos,
+            vec![
+                (work.join("github.com/acme/tool"), "acme/tool".to_string()),
+                (
+                    tree.join("github.com/allonsy/gclone"),
+                    "allonsy/gclone".to_string()
+                ),
+                (
+                    tree.join("gitlab.com/group/sub/repo"),
+                    "gitlab.com:group/sub/repo".to_string()
+                ),
+            ]
+        );
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use crate::cli::UpdateOptions;
+use crate::git;
+use crate::interrupt::InterruptGuard;
+use crate::list;
+use gclone::{Config, Error, Repo, Result};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Fetches (and fast-forwards, with `--pull`) the repos of the tree that the
+/// filter of `options` lets through, running several gits at once.
+pub fn update(conf: &Config, options: &UpdateOptions) -> Result<()> {
+    let repos = list::find_repos(conf, &options.filter)?;
+    if repos.is_empty() {
+        eprintln!("No repos to update");
+        return Ok(());
+    }
+    let jobs = options
+        .jobs
+        .unwrap_or_else(|| conf.get_update_jobs())
+        .min(repos.len());
+
+    let interrupt_guard = InterruptGuard::new()?;
+    let mut progress = Progress::new(repos.len(), io::stderr().is_terminal());
+    let next_index = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (repos, next_index, interrupt_guard) = (&repos, &next_index, &interrupt_guard);
+            scope.spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                let (dir, repo) = match repos.get(index) {
+                    Some(entry) if !interrupt_guard.is_interrupted() => entry,
+                    _ => break,
+                };
+                let name = repo.get_shorthand(conf);
+                let result = git::update(interrupt_guard, dir, &name, options.pull);
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        for (index, result) in receiver {
+            progress.report(conf, &repos[index], result);
+        }
+    });
+    progress.finish()
+}
+
+/// The outcome of the updates so far. On a terminal, a summary line is kept
+/// up to date below the failures. Otherwise every repo gets a line.
+struct Progress {
+    total: usize,
+    done: usize,
+    failed: Vec<String>,
+    interrupted: bool,
+    is_terminal: bool,
+}
+
+impl Progress {
+    fn new(total: usize, is_terminal: bool) -> Progress {
+        Progress {
+            total,
+            done: 0,
+            failed: Vec::new(),
+            interrupted: false,
+            is_terminal,
+        }
+    }
+
+    fn report(
+        &mut self,
+        conf: &Config,
+        (dir, repo): &(PathBuf, Repo),
+        result: Result<std::result::Result<git::Updated, git::Failure>>,
+    ) {
+        let (failure, note) = match result {
+            Ok(Ok(git::Updated::NoUpstream)) => (None, Some("no upstream to fast-forward to")),
+            Ok(Ok(_)) => (None, None),
+            Ok(Err(failure)) => (Some(failure.to_string()), None),
+            Err(Error::Interrupted) => {
+                self.interrupted = true;
+                return;
+            }
+            Err(err) => (Some(err.to_string()), None),
+        };
+        self.done += 1;
+
+        let name = repo.get_shorthand(conf);
+        if self.is_terminal {
+            eprint!("\r\x1b[K");
+        }
+        match failure {
+            Some(failure) => {
+                eprintln!("failed to update {} ({}): {}", name, dir.display(), failure);
+                self.failed.push(name);
+            }
+            None => match note {
+                Some(note) => eprintln!("updated {} ({}): {}", name, dir.display(), note),
+                None if !self.is_terminal => eprintln!("updated {}", name),
+                None => {}
+            },
+        }
+        if self.is_terminal {
+            eprint!(
+                "[{}/{}] updated {}, {} failed",
+                self.done,
+                self.total,
+                self.done - self.failed.len(),
+                self.failed.len()
+            );
+            let _ = io::stderr().flush();
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.is_terminal {
+            eprintln!();
+        }
+        if self.interrupted {
+            return Err(Error::Interrupted);
+        }
+        if !self.failed.is_empty() {
+            return Err(Error::Git(format!(
+                "{} of {} repos failed to update: {}",
+                self.failed.len(),
+                self.total,
+                self.failed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Progress;
+    use crate::git::Updated;
+    use gclone::{Config, Error, Repo};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_progress() {
+        let conf = Config::default();
+        let entry = |url: &str| (PathBuf::from("/git"), Repo::parse(url, &conf).unwrap());
+
+        let mut progress = Progress::new(3, false);
+        progress.report(&conf, &entry("allonsy/gclone"), Ok(Ok(Updated::Fetched)));
+        progress.report(
+            &conf,
+            &entry("gitlab.com:group/repo"),
+            Err(Error::Git("Failed to spawn git process".to_string())),
+        );
+        progress.report(&conf, &entry("acme/tool"), Ok(Ok(Updated::NoUpstream)));
+        assert_eq!(progress.done, 3);
+        match progress.finish() {
+            Err(Error::Git(message)) => {
+                assert_eq!(
+                    message,
+                    "1 of 3 repos failed to update: gitlab.com:group/repo"
+                )
+            }
+            _ => panic!("a failed update should be reported"),
+        }
+
+        let mut progress = Progress::new(2, false);
+        progress.report(&conf, &entry("allonsy/gclone"), Err(Error::Interrupted));
+        assert!(matches!(progress.finish(), Err(Error::Interrupted)));
+        assert!(Progress::new(0, false).finish().is_ok());
+    }
+}